@@ -0,0 +1,156 @@
+/*
+benches/vm.rs: Throughput benchmarks for the CCIL Virtual Machine
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Run with `cargo bench`. Each benchmark executes a chunk a few times and
+// reports the best run, in millions of instructions per second.
+
+use std::{cell::RefCell, hint::black_box, time::{Duration, Instant}};
+
+use ccil::vm::VirtualMachine;
+use ccil::compiler::Compiler;
+use ccil::optimizer::{fold::fold_constants, optimize};
+use ccil::parser::{Parser, token::Token};
+use ccil::vm::chunk::{Chunk, OperandEncoding};
+use ccil::vm::decoded::DecodedChunk;
+use ccil::vm::opcode::OpCodeLookup;
+use ccil::vm::register::RegisterMachine;
+
+const RUNS: usize = 5;
+
+struct Benchmark {
+    name: &'static str,
+    chunk: Vec<u8>,
+    instructions: usize
+}
+
+fn assemble(ops: &[(&str, &[i32])]) -> Vec<u8> {
+    let lookup = OpCodeLookup::new();
+    let mut chunk = Vec::<u8>::new();
+    for (symbol, args) in ops {
+        chunk.write_op(lookup.from_symbol(symbol).unwrap());
+        for arg in *args {
+            chunk.write_arg(*arg);
+        }
+    }
+    chunk
+}
+
+/// Straight-line stack arithmetic: each block pushes two constants, combines
+/// them and discards the result.
+fn arithmetic(blocks: usize) -> Benchmark {
+    let mut ops = Vec::<(&str, &[i32])>::new();
+    for i in 0..blocks {
+        let combine = ["ADD", "SUB", "BXOR", "BAND"][i % 4];
+        ops.extend([("CONST", &[7][..]), ("CONST", &[3][..]), (combine, &[][..]), ("POP", &[][..])]);
+    }
+    Benchmark { name: "arithmetic", chunk: assemble(&ops), instructions: ops.len() }
+}
+
+/// A counting loop of CONST, SUB, COPY and IFNZ per iteration.
+fn counting_loop(iterations: i32) -> Benchmark {
+    // offsets: CONST n at 0, loop body at 5
+    let chunk = assemble(&[
+        ("CONST", &[iterations]),
+        ("CONST", &[1]),
+        ("SUB", &[]),
+        ("COPY", &[0]),
+        ("IFNZ", &[5]),
+        ("POP", &[])
+    ]);
+    Benchmark { name: "loop", chunk, instructions: 2 + 4 * iterations as usize }
+}
+
+/// The counting loop after the peephole optimizer fuses it into ADDC and
+/// COPYIFNZ. Rates count the instructions of the unfused loop, so they
+/// compare directly with `loop`.
+fn fused_loop(iterations: i32) -> Benchmark {
+    let unfused = counting_loop(iterations);
+    let chunk = optimize(&unfused.chunk, OperandEncoding::Fixed, &[], &[], &OpCodeLookup::new()).unwrap().code;
+    Benchmark { name: "fused loop", chunk, instructions: unfused.instructions }
+}
+
+/// A loop that calls a small function on every iteration.
+fn call_loop(iterations: i32) -> Benchmark {
+    // offsets: CONST n at 0, loop at 5, function at 32, end at 38
+    let chunk = assemble(&[
+        ("CONST", &[iterations]),
+        ("CALL", &[32]),
+        ("CONST", &[1]),
+        ("SUB", &[]),
+        ("COPY", &[0]),
+        ("IFNZ", &[5]),
+        ("POP", &[]),
+        ("JUMP", &[38]),
+        ("NOP", &[]),
+        ("RETURN", &[0])
+    ]);
+    Benchmark { name: "call loop", chunk, instructions: 3 + 7 * iterations as usize }
+}
+
+fn run(benchmark: &Benchmark) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let string_pool = RefCell::new(Vec::<u8>::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        let chunk = benchmark.chunk.clone();
+        let start = Instant::now();
+        vm.execute(black_box(chunk));
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+/// Runs the same straight-line source on the stack and register machines.
+/// The stack machine's time includes decoding its chunk, as it always does.
+fn compare_engines(statements: usize) {
+    let mut source = String::from("a = 1; b = 2; c = 3;");
+    for _ in 0..statements / 3 {
+        source += "a = (a + b * c) % 10007; b = a % 97 - 7; c = b - c % 13;";
+    }
+    let mut parser = Parser::new(Token::full_scan(&source));
+    parser.full_parse();
+    let expressions = fold_constants(parser.expressions);
+
+    let compiler = Compiler::new();
+    let chunk = compiler.compile(&expressions);
+    let instructions = DecodedChunk::decode(&chunk, OperandEncoding::Fixed, &OpCodeLookup::new()).unwrap().ops.len();
+    let stack = Benchmark { name: "stack", chunk, instructions };
+    report(stack.name, stack.instructions, run(&stack));
+
+    let program = compiler.compile_registers(&expressions);
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut vm = RegisterMachine::new(&compiler.string_pool);
+        let start = Instant::now();
+        vm.execute(black_box(&program));
+        best = best.min(start.elapsed());
+    }
+    report("register", program.ops.len(), best);
+}
+
+fn report(name: &str, instructions: usize, elapsed: Duration) {
+    let rate = instructions as f64 / elapsed.as_secs_f64() / 1e6;
+    println!("{:<12} {:>10} instructions {:>10.2?} {:>8.1} Minstr/s", name, instructions, elapsed, rate);
+}
+
+fn main() {
+    for benchmark in [arithmetic(250_000), counting_loop(1_000_000), fused_loop(1_000_000), call_loop(500_000)] {
+        report(benchmark.name, benchmark.instructions, run(&benchmark));
+    }
+    compare_engines(30_000);
+}
@@ -0,0 +1,707 @@
+/*
+asm.rs: The CCIL Assembler
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{error::Error, fmt, path::Path};
+
+use rustc_hash::FxHashMap;
+
+use crate::compiler::VariableId;
+use crate::constants::{fileno_const, header_flag_const, type_id_const};
+use crate::linker::{ObjectChunk, Relocation, RelocationKind};
+use crate::vm::chunk::{Chunk, ChunkOffset, OperandEncoding};
+use crate::vm::opcode::{Argument, OpCode, OpCodeLookup};
+use crate::vm::section::{Executable, SymbolTable};
+
+pub mod expr;
+pub mod listing;
+pub mod preprocess;
+
+use expr::{Expr, Value};
+use listing::{ListedName, ListingLine, NameKind};
+use preprocess::{Preprocessor, SourceLine, Token, is_name, parse_string_literal};
+
+// compact operands change size as the labels they refer to move, so layout
+// repeats until every label settles
+const MAX_LAYOUT_PASSES: usize = 16;
+
+// directives handled after preprocessing, plus the preprocessor's own, for suggestions
+const DIRECTIVES: &[&str] = &[
+    ".string", ".data", ".equ", ".global", ".extern", ".variable",
+    ".object", ".sectioned", ".encoding", ".timestamp", ".from",
+    ".include", ".macro", ".endm"
+];
+
+/// A 1-based line and column in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize
+}
+
+/// A problem with one token of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub location: SourceLocation,
+    /// The offending token, as written.
+    pub token: String,
+    pub message: String,
+    /// A likely intended spelling of the token, if one is close.
+    pub suggestion: Option<String>,
+    /// The macros this token was expanded from and where, innermost first.
+    pub expanded_from: Vec<(String, SourceLocation)>
+}
+
+/// The output of a successful assembly.
+pub struct Assembly {
+    pub code: Vec<u8>,
+    pub string_pool: Vec<u8>,
+    /// `.global` names and their code offsets, in definition order.
+    pub exports: Vec<(String, ChunkOffset)>,
+    /// Operands for the linker to patch; only recorded when assembling an object.
+    pub relocations: Vec<Relocation>,
+    /// `.variable` names and ids, in definition order.
+    pub variables: Vec<(String, VariableId)>,
+    pub encoding: OperandEncoding,
+    /// Whether to write a relocatable object, from `set_object` or `.object`.
+    pub object: bool,
+    /// Whether to write a sectioned executable even without strings or symbols, from `.sectioned`.
+    pub sectioned: bool,
+    /// Whether to flag the output as assembled; `.from source` clears it.
+    pub from_assembly: bool,
+    /// The header timestamp requested by `.timestamp`.
+    pub timestamp: Option<i64>,
+    /// Every line after preprocessing and the code it assembled to, for listings.
+    pub lines: Vec<ListingLine>,
+    /// Every name the source defined or imported, sorted by name, for listings.
+    pub names: Vec<ListedName>
+}
+
+pub struct Assembler<'a> {
+    lookup: OpCodeLookup<'a>,
+    operand_encoding: OperandEncoding,
+    object: bool
+}
+
+// names accepted in place of numbers for particular operands
+const TYPE_NAMES: &[(&str, Argument)] = &[
+    ("null", type_id_const::NULL),
+    ("number", type_id_const::NUMBER),
+    ("string", type_id_const::STRING),
+    ("float", type_id_const::FLOAT),
+    ("boolean", type_id_const::BOOLEAN),
+    ("unknown", type_id_const::UNKNOWN)
+];
+const FILENO_NAMES: &[(&str, Argument)] = &[
+    ("stdin", fileno_const::STDIN),
+    ("stdout", fileno_const::STDOUT),
+    ("stderr", fileno_const::STDERR)
+];
+
+struct Operand<'s> {
+    expr: Expr,
+    token: &'s Token
+}
+
+enum Statement<'s> {
+    Label(&'s str),
+    Instruction { line: &'s SourceLine, opcode: &'s OpCode<'s>, operands: Vec<Operand<'s>> }
+}
+
+/// Names and statements collected from the preprocessed lines.
+struct Program<'s> {
+    statements: Vec<Statement<'s>>,
+    // each line and the index of its first statement
+    line_starts: Vec<(&'s SourceLine, usize)>,
+    // every local name (label, string, data or constant) and where it was first defined
+    names: FxHashMap<&'s str, &'s Token>,
+    labels: FxHashMap<&'s str, ChunkOffset>,
+    constants: FxHashMap<&'s str, Argument>,
+    strings: FxHashMap<&'s str, usize>,
+    string_pool: Vec<u8>,
+    exports: Vec<&'s str>,
+    externs: FxHashMap<&'s str, &'s Token>,
+    variables: Vec<(&'s str, VariableId)>,
+    encoding: OperandEncoding,
+    object: bool,
+    sectioned: bool,
+    from_assembly: bool,
+    timestamp: Option<i64>,
+    errors: Vec<AsmError>
+}
+
+/// One pass of laying out code.
+struct Layout<'s> {
+    code: Vec<u8>,
+    relocations: Vec<Relocation>,
+    // where each label actually landed, which may differ from the offsets the pass assumed
+    labels: FxHashMap<&'s str, ChunkOffset>,
+    // the code offset of each statement, and of the end of the code
+    statement_offsets: Vec<ChunkOffset>,
+    // errors evaluating operands
+    errors: Vec<AsmError>
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl AsmError {
+    pub fn new(location: SourceLocation, token: &str, message: String) -> Self {
+        Self { location, token: token.to_string(), message, suggestion: None, expanded_from: Vec::new() }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        for (name, location) in &self.expanded_from {
+            write!(f, "\n  in macro {} expanded at {}", name, location)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AsmError {}
+
+impl Assembly {
+    /// Converts to a relocatable object. The assembly must have been built in object mode.
+    pub fn into_object(self) -> ObjectChunk {
+        ObjectChunk {
+            code: self.code,
+            string_pool: self.string_pool,
+            exports: self.exports,
+            relocations: self.relocations
+        }
+    }
+
+    /// Converts to an executable whose symbol table lists every `.global` name
+    /// as a function and every `.variable` as a variable.
+    pub fn into_executable(self) -> Executable {
+        Executable {
+            code: self.code,
+            string_pool: self.string_pool,
+            symbols: SymbolTable { functions: self.exports, variables: self.variables },
+            encoding: self.encoding
+        }
+    }
+
+    /// Serializes in the format the assembly asked for: an object, a sectioned
+    /// executable if there are strings or symbols to keep, or else a bare chunk.
+    pub fn into_chunk(self, timestamp: i64) -> Vec<u8> {
+        let flags = if self.from_assembly { header_flag_const::FROM_ASSEMBLY } else { 0 };
+        if self.object {
+            return self.into_object().to_chunk(flags, timestamp);
+        }
+        if self.sectioned || !self.exports.is_empty() || !self.variables.is_empty() || !self.string_pool.is_empty() {
+            return self.into_executable().to_chunk(flags, timestamp);
+        }
+        let encoding = self.encoding;
+        let mut code = self.code;
+        return code.with_header(flags | encoding.header_flag(), timestamp);
+    }
+}
+
+impl Assembler<'_> {
+    pub fn new() -> Self {
+        Self {
+            lookup: OpCodeLookup::new(),
+            operand_encoding: OperandEncoding::Fixed,
+            object: false
+        }
+    }
+
+    pub fn set_operand_encoding(&mut self, encoding: OperandEncoding) {
+        self.operand_encoding = encoding;
+    }
+
+    /// Assembles relocatable objects: records relocations and allows `.extern` names as operands.
+    pub fn set_object(&mut self, object: bool) {
+        self.object = object;
+    }
+
+    /// Assembles a file, resolving `.include`s relative to it. Returns every error found.
+    pub fn assemble_file(&self, path: &Path) -> Result<Assembly, Vec<AsmError>> {
+        self.check_object_encoding(&path.display().to_string())?;
+        let mut preprocessor = Preprocessor::new(&self.lookup);
+        preprocessor.include_file(path, None);
+        return self.assemble(preprocessor);
+    }
+
+    /// Assembles source text, reporting errors against `file_name` and
+    /// resolving `.include`s relative to the working directory.
+    pub fn assemble_source(&self, file_name: &str, source: &str) -> Result<Assembly, Vec<AsmError>> {
+        self.check_object_encoding(file_name)?;
+        let mut preprocessor = Preprocessor::new(&self.lookup);
+        preprocessor.include_source(file_name, source, Path::new(""));
+        return self.assemble(preprocessor);
+    }
+
+    /// Objects need fixed-width operands so that the linker can patch them. The
+    /// mode was set outside the source, so the error points at its first line.
+    fn check_object_encoding(&self, file_name: &str) -> Result<(), Vec<AsmError>> {
+        if self.object && self.operand_encoding != OperandEncoding::Fixed {
+            let location = SourceLocation { file: file_name.to_string(), line: 1, column: 1 };
+            return Err(vec![AsmError::new(location, "", "object chunks require fixed operand encoding".to_string())]);
+        }
+        return Ok(());
+    }
+
+    fn assemble(&self, preprocessor: Preprocessor) -> Result<Assembly, Vec<AsmError>> {
+        let macro_names: Vec<&str> = preprocessor.macro_names().collect();
+        let mut program = Program {
+            statements: Vec::new(),
+            line_starts: Vec::new(),
+            names: FxHashMap::default(),
+            labels: FxHashMap::default(),
+            constants: FxHashMap::default(),
+            strings: FxHashMap::default(),
+            string_pool: Vec::new(),
+            exports: Vec::new(),
+            externs: FxHashMap::default(),
+            variables: Vec::new(),
+            encoding: self.operand_encoding,
+            object: self.object,
+            sectioned: false,
+            from_assembly: true,
+            timestamp: None,
+            errors: preprocessor.errors.clone()
+        };
+
+        // first pass: parse every line, recording names
+        for line in &preprocessor.lines {
+            program.line_starts.push((line, program.statements.len()));
+            self.parse_line(&mut program, line, &macro_names);
+        }
+        self.check_names(&mut program);
+        if !program.errors.is_empty() {
+            program.errors.sort_by(|a, b| (&a.location.file, a.location.line, a.location.column).cmp(&(&b.location.file, b.location.line, b.location.column)));
+            return Err(program.errors);
+        }
+
+        // second pass: lay out code until label offsets settle
+        let mut assembly = Assembly {
+            code: Vec::new(),
+            string_pool: Vec::new(),
+            exports: Vec::new(),
+            relocations: Vec::new(),
+            variables: program.variables.iter().map(|(name, id)| (name.to_string(), *id)).collect(),
+            encoding: program.encoding,
+            object: program.object,
+            sectioned: program.sectioned,
+            from_assembly: program.from_assembly,
+            timestamp: program.timestamp,
+            lines: Vec::new(),
+            names: Vec::new()
+        };
+        let mut layout = self.layout(&program);
+        for _ in 1..MAX_LAYOUT_PASSES {
+            if layout.labels == program.labels {
+                break;
+            }
+            program.labels = std::mem::take(&mut layout.labels);
+            layout = self.layout(&program);
+        }
+        if layout.labels != program.labels {
+            // operands whose size depends on their own labels can keep moving them
+            let mut unsettled: Vec<&Token> = layout.labels.iter()
+                .filter(|(name, offset)| program.labels.get(*name) != Some(offset))
+                .map(|(name, _)| program.names[name])
+                .collect();
+            unsettled.sort_by(|a, b| (&a.location.file, a.location.line, a.location.column).cmp(&(&b.location.file, b.location.line, b.location.column)));
+            return Err(unsettled.iter().map(|token| {
+                AsmError::new(token.location.clone(), &token.text, "label layout did not converge".to_string())
+            }).collect());
+        }
+        program.labels = layout.labels;
+        // expressions are checked once labels have their final values
+        if !layout.errors.is_empty() {
+            return Err(layout.errors);
+        }
+        assembly.lines = program.line_starts.iter().enumerate().map(|(i, (line, first))| {
+            let next = program.line_starts.get(i + 1).map_or(program.statements.len(), |(_, next)| *next);
+            ListingLine::new(line, layout.statement_offsets[*first], layout.statement_offsets[next])
+        }).collect();
+        assembly.names = program.listed_names();
+        (assembly.code, assembly.relocations) = (layout.code, layout.relocations);
+        assembly.exports = program.exports.iter().map(|name| (name.to_string(), program.labels[name])).collect();
+        assembly.string_pool = program.string_pool;
+        return Ok(assembly);
+    }
+
+    fn parse_line<'s>(&'s self, program: &mut Program<'s>, line: &'s SourceLine, macro_names: &[&str]) {
+        // a line may start with any number of label definitions
+        let mut tokens = &line.tokens[..];
+        while let Some(label) = tokens.first().and_then(|token| token.text.strip_suffix(':')).filter(|label| is_name(label)) {
+            if program.define(line, &tokens[0], label) {
+                program.labels.insert(label, 0);
+                program.statements.push(Statement::Label(label));
+            }
+            tokens = &tokens[1..];
+        }
+        let (head, args) = match tokens.split_first() {
+            Some(val) => val,
+            None => return
+        };
+
+        match head.text.as_str() {
+            // .string NAME "text" adds a null-terminated string to the pool
+            ".string" => {
+                if args.len() != 2 || !is_name(&args[0].text) {
+                    program.errors.push(line.error(head, "expected a name and a quoted string for directive .string".to_string()));
+                    return;
+                }
+                match parse_string_literal(&args[1].text) {
+                    Ok(text) => {
+                        if program.define(line, &args[0], &args[0].text) {
+                            program.strings.insert(&args[0].text, program.string_pool.len());
+                        }
+                        program.string_pool.extend_from_slice(text.as_bytes());
+                        program.string_pool.push(0); // null terminator
+                    },
+                    Err(message) => program.errors.push(line.error(&args[1], message))
+                }
+            },
+            // .data NAME BYTE... adds raw bytes to the pool
+            ".data" => {
+                if args.is_empty() || !is_name(&args[0].text) {
+                    program.errors.push(line.error(head, "expected a name for directive .data".to_string()));
+                    return;
+                }
+                if program.define(line, &args[0], &args[0].text) {
+                    program.strings.insert(&args[0].text, program.string_pool.len());
+                }
+                for byte in &args[1..] {
+                    match program.evaluate_constant(&byte.text).map(u8::try_from) {
+                        Ok(Ok(val)) => program.string_pool.push(val),
+                        Ok(Err(_)) => program.errors.push(line.error(byte, format!("data {} is not a byte", byte.text))),
+                        Err(message) => program.errors.push(line.error(byte, message))
+                    }
+                }
+            },
+            // .equ NAME VALUE names a constant for later operands
+            ".equ" => {
+                if args.len() != 2 || !is_name(&args[0].text) {
+                    program.errors.push(line.error(head, "expected a name and a value for directive .equ".to_string()));
+                    return;
+                }
+                let value = match program.evaluate_constant(&args[1].text) {
+                    Ok(val) => val,
+                    Err(message) => {
+                        program.errors.push(line.error(&args[1], message));
+                        return;
+                    }
+                };
+                if program.define(line, &args[0], &args[0].text) {
+                    program.constants.insert(&args[0].text, value);
+                }
+            },
+            // linker directives: .global exports (and labels) the next instruction, .extern declares an import
+            ".global" | ".extern" => {
+                if args.len() != 1 || !is_name(&args[0].text) {
+                    program.errors.push(line.error(head, format!("expected 1 name for directive {}", head.text)));
+                    return;
+                }
+                let name = args[0].text.as_str();
+                if head.text == ".extern" {
+                    program.externs.insert(name, &args[0]);
+                } else if program.define(line, &args[0], name) {
+                    program.labels.insert(name, 0);
+                    program.statements.push(Statement::Label(name));
+                    program.exports.push(name);
+                }
+            },
+            // .variable NAME ID names a global variable id for LOAD and STORE
+            ".variable" => {
+                if args.len() != 2 || !is_name(&args[0].text) {
+                    program.errors.push(line.error(head, "expected a name and an id for directive .variable".to_string()));
+                    return;
+                }
+                match program.evaluate_constant(&args[1].text) {
+                    Ok(id) => {
+                        if program.define(line, &args[0], &args[0].text) {
+                            program.variables.push((&args[0].text, id));
+                        }
+                    },
+                    Err(message) => program.errors.push(line.error(&args[1], message))
+                }
+            },
+            // output format directives, so disassembled files reassemble to the same bytes
+            ".object" | ".sectioned" if !args.is_empty() => {
+                program.errors.push(line.error(&args[0], format!("directive {} takes no args", head.text)));
+            },
+            ".object" => {
+                if program.encoding == OperandEncoding::Compact {
+                    program.errors.push(line.error(head, "object files require fixed-width operands".to_string()));
+                }
+                program.object = true;
+            },
+            ".sectioned" => program.sectioned = true,
+            ".encoding" => match args.iter().map(|arg| arg.text.as_str()).collect::<Vec<_>>()[..] {
+                ["fixed"] => program.encoding = OperandEncoding::Fixed,
+                ["compact"] if program.object => program.errors.push(line.error(&args[0], "object files require fixed-width operands".to_string())),
+                ["compact"] => program.encoding = OperandEncoding::Compact,
+                _ => program.errors.push(line.error(head, "expected fixed or compact for directive .encoding".to_string()))
+            },
+            ".timestamp" => match args.iter().map(|arg| arg.text.parse::<u32>()).collect::<Vec<_>>()[..] {
+                [Ok(timestamp)] => program.timestamp = Some(timestamp.into()),
+                _ => program.errors.push(line.error(head, "expected a Unix timestamp for directive .timestamp".to_string()))
+            },
+            ".from" => match args.iter().map(|arg| arg.text.as_str()).collect::<Vec<_>>()[..] {
+                ["assembly"] => program.from_assembly = true,
+                ["source"] => program.from_assembly = false,
+                _ => program.errors.push(line.error(head, "expected assembly or source for directive .from".to_string()))
+            },
+            directive if directive.starts_with('.') => {
+                let mut error = line.error(head, format!("unknown directive {}", directive));
+                error.suggestion = suggest(directive, DIRECTIVES.iter().copied());
+                program.errors.push(error);
+            },
+            mnemonic => {
+                let opcode = match self.lookup.from_symbol(mnemonic) {
+                    Some(opcode) => opcode,
+                    None => {
+                        let mut error = line.error(head, format!("unknown opcode {}", mnemonic));
+                        let candidates: Vec<String> = self.lookup.symbols().map(|symbol| symbol.to_lowercase()).collect();
+                        error.suggestion = suggest(mnemonic, candidates.iter().map(|symbol| symbol.as_str()).chain(macro_names.iter().copied()));
+                        program.errors.push(error);
+                        return;
+                    }
+                };
+                if opcode.num_params != args.len() {
+                    let message = format!("expected {} args for opcode {} but got {}", opcode.num_params, mnemonic, args.len());
+                    program.errors.push(line.error(head, message));
+                    return;
+                }
+
+                let mut operands = Vec::<Operand>::new();
+                for (arg_index, arg) in args.iter().enumerate() {
+                    let named = operand_names(opcode.symbol, arg_index).iter()
+                        .find(|(name, _)| arg.text.eq_ignore_ascii_case(name));
+                    let expr = match named {
+                        Some((_, value)) => Expr::Number(*value as i64),
+                        None => match Expr::parse(&arg.text) {
+                            Ok(expr) => expr,
+                            Err(message) => {
+                                program.errors.push(line.error(arg, message));
+                                continue;
+                            }
+                        }
+                    };
+                    operands.push(Operand { expr, token: arg });
+                }
+                program.statements.push(Statement::Instruction { line, opcode, operands });
+            }
+        }
+    }
+
+    /// Every name used in an operand must be a constant, a label, a string or an import.
+    fn check_names(&self, program: &mut Program) {
+        for statement in &program.statements {
+            let (line, operands) = match statement {
+                Statement::Instruction { line, operands, .. } => (line, operands),
+                Statement::Label(_) => continue
+            };
+            for operand in operands {
+                for name in operand.expr.names() {
+                    if program.externs.contains_key(name) {
+                        if let Some(definition) = program.names.get(name) {
+                            let message = format!("{} is both a local name (defined at {}) and an external symbol", name, definition.location);
+                            program.errors.push(line.error(operand.token, message));
+                        } else if !program.object {
+                            program.errors.push(line.error(operand.token, format!("external symbol {} requires an object file", name)));
+                        }
+                    } else if !program.names.contains_key(name) {
+                        let mut error = line.error(operand.token, format!("undefined label {}", name));
+                        let mut candidates: Vec<&str> = program.names.keys().chain(program.externs.keys()).copied().collect();
+                        candidates.sort();
+                        error.suggestion = suggest(name, candidates.into_iter());
+                        program.errors.push(error);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encodes every statement using the current label offsets.
+    fn layout<'s>(&self, program: &Program<'s>) -> Layout<'s> {
+        let mut code = Vec::<u8>::new();
+        let mut relocations = Vec::<Relocation>::new();
+        let mut labels = FxHashMap::<&str, ChunkOffset>::default();
+        let mut statement_offsets = Vec::<ChunkOffset>::with_capacity(program.statements.len() + 1);
+        let mut errors = Vec::<AsmError>::new();
+        let resolve = |name: &str| program.resolve(name);
+
+        for statement in &program.statements {
+            statement_offsets.push(code.len());
+            match statement {
+                Statement::Label(label) => {
+                    labels.insert(label, code.len());
+                },
+                Statement::Instruction { line, opcode, operands } => {
+                    code.write_op(opcode);
+                    let is_branch = matches!(opcode.symbol, "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL");
+                    for (arg_index, operand) in operands.iter().enumerate() {
+                        let value = operand.expr.evaluate(&resolve);
+                        let (argument, base) = match value.and_then(|value| Ok((value.to_argument()?, value.base))) {
+                            Ok(val) => val,
+                            Err(message) => {
+                                errors.push(line.error(operand.token, message));
+                                (0, None)
+                            }
+                        };
+                        // numeric branch targets in objects are offsets into the object's own code
+                        let kind = match base {
+                            None if is_branch && arg_index == 0 => Some(RelocationKind::Code),
+                            base => base
+                        };
+                        if let Some(kind) = kind.filter(|_| program.object) {
+                            relocations.push(Relocation { offset: code.len(), kind });
+                        }
+                        code.write_operand(argument, program.encoding);
+                    }
+                }
+            }
+        }
+
+        statement_offsets.push(code.len());
+        return Layout { code, relocations, labels, statement_offsets, errors };
+    }
+}
+
+impl<'s> Program<'s> {
+    /// Records a new local name, reporting an error if it already exists.
+    fn define(&mut self, line: &SourceLine, token: &'s Token, name: &'s str) -> bool {
+        if let Some(first) = self.names.get(name) {
+            let message = format!("duplicate name {} (first defined at {})", name, first.location);
+            self.errors.push(line.error(token, message));
+            return false;
+        }
+        self.names.insert(name, token);
+        return true;
+    }
+
+    /// Looks up the value of a name in an operand expression.
+    fn resolve(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.constants.get(name) {
+            return Some(Value { number: *value as i64, base: None });
+        }
+        if let Some(offset) = self.strings.get(name) {
+            return Some(Value { number: *offset as i64, base: Some(RelocationKind::String) });
+        }
+        if let Some(offset) = self.labels.get(name) {
+            return Some(Value { number: *offset as i64, base: Some(RelocationKind::Code) });
+        }
+        if let Some((_, id)) = self.variables.iter().find(|(variable, _)| *variable == name) {
+            return Some(Value { number: *id as i64, base: Some(RelocationKind::Variable) });
+        }
+        if self.externs.contains_key(name) {
+            return Some(Value { number: 0, base: Some(RelocationKind::Symbol(name.to_string())) });
+        }
+        return None;
+    }
+
+    /// Every name with its kind and value, sorted by name.
+    fn listed_names(&self) -> Vec<ListedName> {
+        let mut names = Vec::<ListedName>::new();
+        for (name, offset) in &self.labels {
+            let kind = if self.exports.contains(name) { NameKind::Global } else { NameKind::Label };
+            names.push(ListedName { name: name.to_string(), kind, value: *offset as i64 });
+        }
+        for (name, offset) in &self.strings {
+            names.push(ListedName { name: name.to_string(), kind: NameKind::String, value: *offset as i64 });
+        }
+        for (name, value) in &self.constants {
+            names.push(ListedName { name: name.to_string(), kind: NameKind::Constant, value: *value as i64 });
+        }
+        for (name, id) in &self.variables {
+            names.push(ListedName { name: name.to_string(), kind: NameKind::Variable, value: *id as i64 });
+        }
+        for name in self.externs.keys() {
+            names.push(ListedName { name: name.to_string(), kind: NameKind::Extern, value: 0 });
+        }
+        names.sort_by(|a, b| a.name.cmp(&b.name));
+        return names;
+    }
+
+    /// Evaluates an expression that may only use numbers and constants defined so far.
+    fn evaluate_constant(&self, text: &str) -> Result<Argument, String> {
+        let value = Expr::parse(text)?.evaluate(&|name| {
+            self.constants.get(name).map(|value| Value { number: *value as i64, base: None })
+        });
+        return match value {
+            Ok(value) => value.to_argument(),
+            Err(_) if Expr::parse(text)?.names().iter().any(|name| !self.constants.contains_key(name)) => {
+                Err(format!("{} is not a constant expression", text))
+            },
+            Err(message) => Err(message)
+        };
+    }
+}
+
+/// Names that may be written in place of a number for an opcode's operand,
+/// such as `number` for the type of a `STORE` or `stdout` for a `WRITE`.
+pub fn operand_names(opcode_symbol: &str, arg_index: usize) -> &'static [(&'static str, Argument)] {
+    match (opcode_symbol, arg_index) {
+        ("STORE", 1) => TYPE_NAMES,
+        ("WRITE" | "WRITES", 0) => FILENO_NAMES,
+        _ => &[]
+    }
+}
+
+/// Finds the candidate closest to `word`, if any is close enough to be a likely typo.
+fn suggest<'c>(word: &str, candidates: impl Iterator<Item = &'c str>) -> Option<String> {
+    let word = word.to_lowercase();
+    let max_distance = (word.chars().count() / 3).clamp(1, 2);
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(&word, &candidate.to_lowercase());
+        if distance <= max_distance && best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            best = Some((distance, candidate));
+        }
+    }
+    return best.map(|(_, candidate)| candidate.to_string());
+}
+
+/// Edit distance counting insertions, deletions, substitutions and adjacent transpositions.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    return distances[a.len()][b.len()];
+}
@@ -0,0 +1,204 @@
+/*
+asm/expr.rs: Operand expressions for the CCIL Assembler
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::linker::RelocationKind;
+use crate::vm::opcode::Argument;
+
+/// An operand expression, such as `label+5` or `SIZE*2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Name(String),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>)
+}
+
+/// The result of evaluating an expression: a number, optionally relative to
+/// an address that is only known once the program is linked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub number: i64,
+    pub base: Option<RelocationKind>
+}
+
+impl Expr {
+    /// Parses an expression written without spaces. Supports decimal, `0x` hex,
+    /// `0b` binary and `'c'` character literals, names, parentheses, unary `-`
+    /// and `+ - * / %` with the usual precedence.
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut index = 0;
+        let expr = parse_sum(&chars, &mut index)?;
+        if index != chars.len() {
+            return Err(format!("unexpected {} in expression {}", chars[index], text));
+        }
+        return Ok(expr);
+    }
+
+    /// Every name the expression refers to.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) => Vec::new(),
+            Expr::Name(name) => vec![name.as_str()],
+            Expr::Negate(inner) => inner.names(),
+            Expr::Binary(_, left, right) => {
+                let mut names = left.names();
+                names.extend(right.names());
+                names
+            }
+        }
+    }
+
+    /// Evaluates the expression, looking names up with `resolve`.
+    pub fn evaluate(&self, resolve: &dyn Fn(&str) -> Option<Value>) -> Result<Value, String> {
+        match self {
+            Expr::Number(number) => Ok(Value { number: *number, base: None }),
+            Expr::Name(name) => resolve(name).ok_or_else(|| format!("undefined label {}", name)),
+            Expr::Negate(inner) => {
+                let value = inner.evaluate(resolve)?;
+                if value.base.is_some() {
+                    return Err("cannot negate an address".to_string());
+                }
+                Ok(Value { number: value.number.checked_neg().ok_or("expression overflows")?, base: None })
+            },
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(resolve)?, right.evaluate(resolve)?);
+                let number = match op {
+                    '+' => left.number.checked_add(right.number),
+                    '-' => left.number.checked_sub(right.number),
+                    '*' => left.number.checked_mul(right.number),
+                    _ if right.number == 0 => return Err("division by zero in expression".to_string()),
+                    '/' => left.number.checked_div(right.number),
+                    _ => left.number.checked_rem(right.number)
+                }.ok_or("expression overflows")?;
+                // an address plus or minus a number is still an address, and the
+                // distance between two addresses of the same kind is a number
+                let base = match (op, left.base, right.base) {
+                    (_, None, None) => None,
+                    ('+' | '-', Some(base), None) => Some(base),
+                    ('+', None, Some(base)) => Some(base),
+                    ('-', Some(a), Some(b)) if a == b && !matches!(a, RelocationKind::Symbol(_)) => None,
+                    _ => return Err(format!("cannot apply {} to an address", op))
+                };
+                Ok(Value { number, base })
+            }
+        }
+    }
+}
+
+impl Value {
+    /// The value as an operand, if it fits.
+    pub fn to_argument(&self) -> Result<Argument, String> {
+        return Argument::try_from(self.number).map_err(|_| format!("{} does not fit in an operand", self.number));
+    }
+}
+
+fn parse_sum(chars: &[char], index: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_product(chars, index)?;
+    while let Some(&op) = chars.get(*index).filter(|c| **c == '+' || **c == '-') {
+        *index += 1;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(parse_product(chars, index)?));
+    }
+    return Ok(expr);
+}
+
+fn parse_product(chars: &[char], index: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_unary(chars, index)?;
+    while let Some(&op) = chars.get(*index).filter(|c| matches!(c, '*' | '/' | '%')) {
+        *index += 1;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(parse_unary(chars, index)?));
+    }
+    return Ok(expr);
+}
+
+fn parse_unary(chars: &[char], index: &mut usize) -> Result<Expr, String> {
+    match chars.get(*index) {
+        Some('-') => {
+            *index += 1;
+            // fold negative literals so that i32::MIN can be written directly
+            return Ok(match parse_unary(chars, index)? {
+                Expr::Number(number) => Expr::Number(-number),
+                inner => Expr::Negate(Box::new(inner))
+            });
+        },
+        Some('(') => {
+            *index += 1;
+            let expr = parse_sum(chars, index)?;
+            if chars.get(*index) != Some(&')') {
+                return Err("expected ) in expression".to_string());
+            }
+            *index += 1;
+            return Ok(expr);
+        },
+        Some('\'') => return parse_char(chars, index),
+        Some(c) if c.is_ascii_digit() => return parse_number(chars, index),
+        Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+            let start = *index;
+            while chars.get(*index).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                *index += 1;
+            }
+            return Ok(Expr::Name(chars[start..*index].iter().collect()));
+        },
+        Some(c) => return Err(format!("unexpected {} in expression", c)),
+        None => return Err("expected a value in expression".to_string())
+    }
+}
+
+fn parse_number(chars: &[char], index: &mut usize) -> Result<Expr, String> {
+    let (radix, prefix) = match (chars[*index], chars.get(*index + 1)) {
+        ('0', Some('x' | 'X')) => (16, 2),
+        ('0', Some('b' | 'B')) => (2, 2),
+        _ => (10, 0)
+    };
+    *index += prefix;
+    let start = *index;
+    while chars.get(*index).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        *index += 1;
+    }
+    let digits: String = chars[start..*index].iter().filter(|c| **c != '_').collect();
+    return match i64::from_str_radix(&digits, radix) {
+        Ok(number) if !digits.is_empty() => Ok(Expr::Number(number)),
+        _ => Err(format!("invalid number {}", chars[start - prefix..*index].iter().collect::<String>()))
+    };
+}
+
+fn parse_char(chars: &[char], index: &mut usize) -> Result<Expr, String> {
+    let value = match (chars.get(*index + 1), chars.get(*index + 2)) {
+        (Some('\\'), Some(escape)) => {
+            *index += 3;
+            match escape {
+                'n' => '\n',
+                't' => '\t',
+                '0' => '\0',
+                '\\' => '\\',
+                '\'' => '\'',
+                other => return Err(format!("unknown escape \\{}", other))
+            }
+        },
+        (Some(c), _) if *c != '\'' => {
+            *index += 2;
+            *c
+        },
+        _ => return Err("empty character literal".to_string())
+    };
+    if chars.get(*index) != Some(&'\'') {
+        return Err("unterminated character literal".to_string());
+    }
+    *index += 1;
+    return Ok(Expr::Number(value as i64));
+}
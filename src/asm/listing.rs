@@ -0,0 +1,118 @@
+/*
+asm/listing.rs: Assembler listings
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+
+use crate::asm::{Assembly, SourceLocation};
+use crate::asm::preprocess::SourceLine;
+use crate::vm::chunk::ChunkOffset;
+
+// bytes of code shown on each line of a listing
+const LISTING_ROW_BYTES: usize = 8;
+
+/// One line of source after preprocessing, and the code it assembled to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    /// Where the line was written; for macro bodies, where the outermost macro was expanded.
+    pub location: SourceLocation,
+    /// Whether the line came from a macro body.
+    pub expanded: bool,
+    /// The line's tokens, separated by single spaces.
+    pub text: String,
+    pub offset: ChunkOffset,
+    pub end: ChunkOffset
+}
+
+/// What a name in a listing refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Label,
+    /// A label exported by `.global`.
+    Global,
+    String,
+    Constant,
+    Variable,
+    Extern
+}
+
+/// A name defined or imported by the source, with its code offset, pool
+/// offset, value or id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedName {
+    pub name: String,
+    pub kind: NameKind,
+    pub value: i64
+}
+
+impl ListingLine {
+    pub fn new(line: &SourceLine, offset: ChunkOffset, end: ChunkOffset) -> Self {
+        let location = match line.expanded_from.last() {
+            Some((_, location)) => location.clone(),
+            None => line.tokens[0].location.clone()
+        };
+        let text: Vec<&str> = line.tokens.iter().map(|token| token.text.as_str()).collect();
+        Self { location, expanded: !line.expanded_from.is_empty(), text: text.join(" "), offset, end }
+    }
+}
+
+impl fmt::Display for NameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameKind::Label => write!(f, "label"),
+            NameKind::Global => write!(f, "global"),
+            NameKind::String => write!(f, "string"),
+            NameKind::Constant => write!(f, "constant"),
+            NameKind::Variable => write!(f, "variable"),
+            NameKind::Extern => write!(f, "extern")
+        }
+    }
+}
+
+impl Assembly {
+    /// Renders a classic assembler listing: each line with its source line
+    /// number, code offset and encoded bytes, then a table of every name.
+    /// Lines expanded from macros are marked with `+`.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let mut file: Option<&str> = None;
+        for line in &self.lines {
+            if file != Some(line.location.file.as_str()) {
+                file = Some(&line.location.file);
+                out += &format!("{}:\n", line.location.file);
+            }
+            let bytes = &self.code[line.offset..line.end];
+            let mut rows = bytes.chunks(LISTING_ROW_BYTES);
+            let marker = if line.expanded { '+' } else { ' ' };
+            out += &format!("{:>5}{} {:04}  {:<24} {}\n", line.location.line, marker, line.offset, hex(rows.next().unwrap_or(&[])), line.text);
+            for (i, row) in rows.enumerate() {
+                out += &format!("{:>6} {:04}  {}\n", "", line.offset + (i + 1) * LISTING_ROW_BYTES, hex(row));
+            }
+        }
+
+        out += "\nNAME                 KIND      VALUE\n";
+        for name in &self.names {
+            out += &format!("{:<20} {:<9} {}\n", name.name, name.kind.to_string(), name.value);
+        }
+        return out;
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    return bytes.join(" ");
+}
@@ -0,0 +1,343 @@
+/*
+asm/preprocess.rs: Tokenizes CCIL assembly and expands includes and macros
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{fs, path::{Path, PathBuf}};
+
+use rustc_hash::FxHashMap;
+
+use crate::asm::{AsmError, SourceLocation};
+use crate::vm::opcode::OpCodeLookup;
+
+/// A whitespace-separated word of assembly, or a whole quoted string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub location: SourceLocation
+}
+
+/// A non-empty line of assembly after includes and macros are expanded.
+/// Lines from a macro body record each expansion they came from, innermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub tokens: Vec<Token>,
+    pub expanded_from: Vec<(String, SourceLocation)>
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>
+}
+
+/// Expands `.include` files and `.macro` invocations into a flat list of lines,
+/// collecting errors rather than stopping at the first.
+pub struct Preprocessor<'a> {
+    opcode_lookup: &'a OpCodeLookup<'a>,
+    macros: FxHashMap<String, Macro>,
+    // canonical paths of the files currently being read, outermost first
+    include_stack: Vec<PathBuf>,
+    // names of the macros currently being expanded, outermost first
+    expansion_stack: Vec<String>,
+    expansion_count: usize,
+    pub lines: Vec<SourceLine>,
+    pub errors: Vec<AsmError>
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(opcode_lookup: &'a OpCodeLookup<'a>) -> Self {
+        Self {
+            opcode_lookup,
+            macros: FxHashMap::default(),
+            include_stack: Vec::new(),
+            expansion_stack: Vec::new(),
+            expansion_count: 0,
+            lines: Vec::new(),
+            errors: Vec::new()
+        }
+    }
+
+    /// Names of every macro defined so far.
+    pub fn macro_names(&self) -> impl Iterator<Item = &str> {
+        return self.macros.keys().map(|name| name.as_str());
+    }
+
+    /// Reads a file, reporting failures at `from` (the path token of an `.include`) if given.
+    pub fn include_file(&mut self, path: &Path, from: Option<&Token>) {
+        let location = match from {
+            Some(token) => token.location.clone(),
+            None => SourceLocation { file: path.display().to_string(), line: 1, column: 1 }
+        };
+        let token = from.map(|token| token.text.clone()).unwrap_or_default();
+        let read_error = |error: std::io::Error| AsmError::new(
+            location.clone(), &token, format!("failed to read {}: {}", path.display(), error)
+        );
+
+        let canonical_path = match fs::canonicalize(path) {
+            Ok(val) => val,
+            Err(error) => return self.errors.push(read_error(error))
+        };
+        if self.include_stack.contains(&canonical_path) {
+            let mut cycle: Vec<String> = self.include_stack.iter()
+                .skip_while(|included| **included != canonical_path)
+                .map(|included| included.display().to_string())
+                .collect();
+            cycle.push(canonical_path.display().to_string());
+            return self.errors.push(AsmError::new(location, &token, format!("include cycle {}", cycle.join(" -> "))));
+        }
+        let source = match fs::read_to_string(path) {
+            Ok(val) => val,
+            Err(error) => return self.errors.push(read_error(error))
+        };
+
+        self.include_stack.push(canonical_path);
+        // included paths are relative to the including file
+        self.include_source(&path.display().to_string(), &source, path.parent().unwrap_or(Path::new("")));
+        self.include_stack.pop();
+    }
+
+    /// Processes source text as if it were a file named `file_name` in `dir`.
+    pub fn include_source(&mut self, file_name: &str, source: &str, dir: &Path) {
+        // the macro being defined, with no name if its .macro line was invalid and the body is skipped
+        let mut defining: Option<(Option<Token>, Macro)> = None;
+        for (i, text) in source.split('\n').enumerate() {
+            let tokens = tokenize(file_name, i + 1, text, &mut self.errors);
+            if tokens.is_empty() {
+                continue;
+            }
+            let line = SourceLine { tokens, expanded_from: Vec::new() };
+            let head = &line.tokens[0];
+
+            if let Some((_, body)) = &mut defining {
+                match head.text.as_str() {
+                    ".endm" => {
+                        if let Some((Some(name), body)) = defining.take() {
+                            self.macros.insert(name.text, body);
+                        }
+                    },
+                    ".macro" => self.errors.push(AsmError::new(head.location.clone(), &head.text, "macros cannot be defined inside a macro".to_string())),
+                    _ => body.body.push(line)
+                }
+                continue;
+            }
+
+            match head.text.as_str() {
+                ".macro" => {
+                    defining = Some(match self.define_macro(&line) {
+                        Some((name, params)) => (Some(name.clone()), Macro { params, body: Vec::new() }),
+                        None => (None, Macro { params: Vec::new(), body: Vec::new() })
+                    });
+                },
+                ".endm" => self.errors.push(AsmError::new(head.location.clone(), &head.text, ".endm without .macro".to_string())),
+                ".include" => {
+                    if line.tokens.len() != 2 {
+                        self.errors.push(AsmError::new(head.location.clone(), &head.text, "expected a quoted path for directive .include".to_string()));
+                        continue;
+                    }
+                    let path_token = &line.tokens[1];
+                    match parse_string_literal(&path_token.text) {
+                        Ok(included) => self.include_file(&dir.join(included), Some(path_token)),
+                        Err(message) => self.errors.push(AsmError::new(path_token.location.clone(), &path_token.text, message))
+                    }
+                },
+                _ => self.emit(line)
+            }
+        }
+
+        if let Some((Some(name), _)) = defining {
+            self.errors.push(AsmError::new(name.location.clone(), &name.text, format!("macro {} is missing .endm", name.text)));
+        }
+    }
+
+    /// Checks a `.macro` line, returning the macro's name and parameters if it is valid.
+    fn define_macro<'l>(&mut self, line: &'l SourceLine) -> Option<(&'l Token, Vec<String>)> {
+        let head = &line.tokens[0];
+        let name = match line.tokens.get(1) {
+            Some(name) if is_name(&name.text) => name,
+            _ => {
+                self.errors.push(AsmError::new(head.location.clone(), &head.text, "expected a name for directive .macro".to_string()));
+                return None;
+            }
+        };
+        if self.opcode_lookup.from_symbol(&name.text).is_some() {
+            self.errors.push(AsmError::new(name.location.clone(), &name.text, format!("macro {} has the same name as an opcode", name.text)));
+            return None;
+        }
+        if self.macros.contains_key(&name.text) {
+            self.errors.push(AsmError::new(name.location.clone(), &name.text, format!("macro {} is already defined", name.text)));
+            return None;
+        }
+        let mut params = Vec::<String>::new();
+        for param in &line.tokens[2..] {
+            if !is_name(&param.text) {
+                self.errors.push(AsmError::new(param.location.clone(), &param.text, format!("invalid macro parameter {}", param.text)));
+                return None;
+            }
+            params.push(param.text.clone());
+        }
+        return Some((name, params));
+    }
+
+    /// Adds a line to the output, expanding it if it invokes a macro.
+    fn emit(&mut self, line: SourceLine) {
+        // labels before a macro invocation stay on their own line
+        let label_count = line.tokens.iter()
+            .take_while(|token| token.text.strip_suffix(':').is_some_and(is_name))
+            .count();
+        let head = match line.tokens.get(label_count) {
+            Some(head) if self.macros.contains_key(&head.text) => head.clone(),
+            _ => {
+                self.lines.push(line);
+                return;
+            }
+        };
+        if label_count > 0 {
+            self.lines.push(SourceLine { tokens: line.tokens[..label_count].to_vec(), expanded_from: line.expanded_from.clone() });
+        }
+
+        let name = head.text.as_str();
+        if self.expansion_stack.iter().any(|expanding| expanding == name) {
+            self.errors.push(line.error(&head, format!("macro {} expands itself", name)));
+            return;
+        }
+        let args = &line.tokens[label_count + 1..];
+        let params = &self.macros[name].params;
+        if params.len() != args.len() {
+            let message = format!("expected {} args for macro {} but got {}", params.len(), name, args.len());
+            self.errors.push(line.error(&head, message));
+            return;
+        }
+
+        // \@ in a macro body becomes a suffix unique to this expansion, for local labels
+        self.expansion_count += 1;
+        let unique_suffix = format!("_{}", self.expansion_count);
+        let mut expanded_from = vec![(name.to_string(), head.location.clone())];
+        expanded_from.extend(line.expanded_from.iter().cloned());
+        let expanded: Vec<SourceLine> = self.macros[name].body.iter()
+            .map(|body_line| SourceLine {
+                tokens: body_line.tokens.iter()
+                    .map(|token| Token {
+                        text: match params.iter().position(|param| *param == token.text) {
+                            Some(index) => args[index].text.clone(),
+                            None => token.text.replace("\\@", &unique_suffix)
+                        },
+                        location: token.location.clone()
+                    })
+                    .collect(),
+                expanded_from: expanded_from.clone()
+            })
+            .collect();
+
+        self.expansion_stack.push(name.to_string());
+        for expanded_line in expanded {
+            self.emit(expanded_line);
+        }
+        self.expansion_stack.pop();
+    }
+}
+
+impl SourceLine {
+    /// Builds an error about one of this line's tokens.
+    pub fn error(&self, token: &Token, message: String) -> AsmError {
+        let mut error = AsmError::new(token.location.clone(), &token.text, message);
+        error.expanded_from = self.expanded_from.clone();
+        return error;
+    }
+}
+
+/// Splits a line into tokens, dropping `//` comments. Quoted strings and character
+/// literals are kept whole, quotes and escapes included. A line with an error has no tokens.
+pub fn tokenize(file_name: &str, line: usize, text: &str, errors: &mut Vec<AsmError>) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::<Token>::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let location = SourceLocation { file: file_name.to_string(), line, column: start + 1 };
+        if chars[index].is_whitespace() {
+            index += 1;
+            continue;
+        }
+        if chars[index] == '/' && chars.get(index + 1) == Some(&'/') {
+            break;
+        }
+
+        if chars[index] == '"' {
+            index += 1;
+            let mut escaped = false;
+            while index < chars.len() && (escaped || chars[index] != '"') {
+                escaped = !escaped && chars[index] == '\\';
+                index += 1;
+            }
+            if index == chars.len() {
+                let token: String = chars[start..].iter().collect();
+                errors.push(AsmError::new(location, &token, "unterminated string".to_string()));
+                return Vec::new();
+            }
+            index += 1;
+        } else {
+            while index < chars.len() && !chars[index].is_whitespace() && !(chars[index] == '/' && chars.get(index + 1) == Some(&'/')) {
+                // character literals may hold spaces, as in ' '
+                if chars[index] == '\'' {
+                    index += match chars.get(index + 1) {
+                        Some('\\') => 3,
+                        Some(_) => 2,
+                        None => 1
+                    };
+                }
+                index += 1;
+            }
+            index = index.min(chars.len());
+        }
+        tokens.push(Token { text: chars[start..index].iter().collect(), location });
+    }
+    return tokens;
+}
+
+/// Whether `name` can be used as a label, string, constant or macro name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+/// Parses a double-quoted literal, supporting `\n`, `\t`, `\0`, `\\` and `\"` escapes.
+pub fn parse_string_literal(literal: &str) -> Result<String, String> {
+    let inner = match literal.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(val) if literal.len() >= 2 => val,
+        _ => return Err(format!("expected a quoted string but got {}", literal))
+    };
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some('0') => text.push('\0'),
+            Some('\\') => text.push('\\'),
+            Some('"') => text.push('"'),
+            Some(other) => return Err(format!("unknown escape \\{}", other)),
+            None => return Err("string ends with a lone \\".to_string())
+        }
+    }
+    return Ok(text);
+}
@@ -16,96 +16,168 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::{cell::RefCell, fs, process::exit};
+use std::{cell::RefCell, fs, io::{self, Write}, path::Path, process::exit};
 
 use clap::Parser;
 
-use ccil::vm::{VirtualMachine, chunk::Chunk, opcode::{OpCode, OpCodeLookup}, stack::StackPointer};
+use ccil::asm::Assembler;
+use ccil::vm::{VirtualMachine, chunk::{Chunk, OperandEncoding, resolve_timestamp}};
 
 /// Quick and dirty assembler for ccil bytecode, supports both writing to file and immediate execution
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Path of ccil assembly file
-    input_path: String,
+    #[arg(required_unless_present = "interactive")]
+    input_path: Option<String>,
+
+    /// Read and execute one line at a time, showing the stack and variables after each
+    #[arg(short, long, default_value_t = false, conflicts_with_all = ["input_path", "execute", "object", "listing"])]
+    interactive: bool,
 
     /// Flag to execute bytecode rather than writing it, will compile otherwise
     #[arg(short, long, default_value_t = false)]
     execute: bool,
 
-    /// Output file (required if compiling, ignored if executing)
+    /// Output file (required if compiling without --listing, ignored if executing)
     #[arg(short, long, default_value_t = String::new())]
-    output_path: String
+    output_path: String,
+
+    /// Encode operands as variable-length integers instead of fixed 4-byte values
+    #[arg(short, long, default_value_t = false)]
+    compact: bool,
+
+    /// Write a relocatable object file for ccill instead of an executable
+    #[arg(long, default_value_t = false)]
+    object: bool,
+
+    /// Header timestamp in UTC Unix seconds (defaults to $SOURCE_DATE_EPOCH, then the current time)
+    #[arg(long, conflicts_with = "no_timestamp")]
+    timestamp: Option<i64>,
+
+    /// Write a zero header timestamp for reproducible output
+    #[arg(long, default_value_t = false)]
+    no_timestamp: bool,
+
+    /// Also write a listing of each line's offset and bytes, and every name, to this file
+    #[arg(long)]
+    listing: Option<String>
 }
 
-fn main() {
-    let opcode_lookup = OpCodeLookup::new();
+// directives whose definitions the REPL keeps for later lines
+const REPL_DEFINITIONS: &[&str] = &[".equ", ".string", ".data", ".variable"];
+
+fn repl(encoding: OperandEncoding) -> ! {
+    let mut assembler = Assembler::new();
+    assembler.set_operand_encoding(encoding);
+    let string_pool = RefCell::new(Vec::<u8>::new());
+    let mut vm = VirtualMachine::new(&string_pool);
+    vm.set_operand_encoding(encoding);
+    // every definition so far, assembled ahead of each line so that its names stay in scope
+    let mut definitions = String::new();
+
+    loop {
+        print!("ccila> ");
+        let _ = io::stdout().flush();
+        let mut buffer = String::new();
+        match io::stdin().read_line(&mut buffer) {
+            Ok(0) => exit(0),
+            Ok(_) => {},
+            Err(_) => { continue; }
+        };
+        let line = buffer.trim();
+        if line.is_empty() {
+            continue;
+        }
 
+        let assembly = match assembler.assemble_source("<stdin>", &format!("{}{}\n", definitions, line)) {
+            Ok(val) => val,
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("Error at column {}: {}", error.location.column, error.message);
+                }
+                continue;
+            }
+        };
+        if line.split_whitespace().next().is_some_and(|head| REPL_DEFINITIONS.contains(&head)) {
+            definitions += line;
+            definitions += "\n";
+        }
+
+        // definitions only ever append to the pool, so earlier offsets stay valid
+        *string_pool.borrow_mut() = assembly.string_pool;
+        if let Err(error) = vm.try_execute(&assembly.code) {
+            eprintln!("{}", error);
+        }
+
+        println!("stack: {:?}", vm.stack());
+        let mut variables: Vec<_> = vm.variables().iter().collect();
+        variables.sort_by_key(|(id, _)| **id);
+        for (id, value) in variables {
+            println!("var {}: {:?}", id, value);
+        }
+    }
+}
+
+fn main() {
     let args = Args::parse();
-    
-    if !args.execute && args.output_path.is_empty() {
+
+    let encoding = if args.compact { OperandEncoding::Compact } else { OperandEncoding::Fixed };
+    if args.interactive {
+        repl(encoding);
+    }
+    let input_path = args.input_path.unwrap();
+
+    if !args.execute && args.output_path.is_empty() && args.listing.is_none() {
         eprintln!("Output file not specified, run --help for more info");
         exit(1);
     }
+    if args.object && (args.execute || args.compact) {
+        eprintln!("Object files cannot be executed and require fixed-width operands");
+        exit(1);
+    }
+    if let Err(error) = fs::metadata(&input_path) {
+        eprintln!("Failed to read input file: {}", error);
+        exit(1);
+    }
 
-    let input_file = match fs::read_to_string(args.input_path) {
+    let mut assembler = Assembler::new();
+    assembler.set_operand_encoding(encoding);
+    assembler.set_object(args.object);
+    let assembly = match assembler.assemble_file(Path::new(&input_path)) {
         Ok(val) => val,
-        Err(error) => {
-            eprintln!("Failed to read input file: {}", error);
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("Error assembling {}", error);
+            }
+            eprintln!("{} error{} assembling {}", errors.len(), if errors.len() == 1 { "" } else { "s" }, input_path);
             exit(1);
         }
     };
 
-    let mut chunk = Vec::<u8>::new();
-    for (i, line) in input_file.split("\n").enumerate() {
-        // strip line of comments and whitespace
-        let stripped_line = line[0..line.find("//").unwrap_or(line.len())].trim();
-        if stripped_line.is_empty() {
-            continue;
-        }
-
-        // split on whitespace
-        let line_split: Vec<&str> = stripped_line.split_whitespace().collect();
-
-        // get opcode from first value of split (guaranteed to exist)
-        let opcode_str = &line_split[0].to_lowercase();
+    if let Some(listing_path) = &args.listing
+        && let Err(error) = fs::write(listing_path, assembly.listing()) {
+        eprintln!("Failed to write listing file: {}", error);
+        exit(1);
+    }
 
-        let line_opcode: &OpCode = match opcode_lookup.from_symbol(opcode_str) {
-            Some(opcode) => opcode,
-            None => panic!(
-                "Error assembling line {}: invalid opcode {}",
-                i, opcode_str
-            )
-        };
-        
-        let num_args = line_opcode.num_params;
-        if num_args != line_split.len() - 1 {
-            eprintln!("Error assembling line {}: expected {} args for opcode {} but got {}", i, num_args, opcode_str, line_split.len() - 1);
+    if args.execute {
+        if assembly.object {
+            eprintln!("Object files cannot be executed");
             exit(1);
         }
-
-        // finally, write instruction
-        chunk.write_op(line_opcode);
-        for arg in &line_split[1..] {
-            let int_arg = match arg.parse::<StackPointer>() {
-                Ok(val) => val,
-                Err(_) => {
-                    eprintln!("Error assembling line {}: arg is not a number", i);
-                    exit(1);
-                }
-            };
-            chunk.write_arg(int_arg);
+        let string_pool = RefCell::new(assembly.string_pool);
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_operand_encoding(assembly.encoding);
+        vm.execute(assembly.code);
+    } else if !args.output_path.is_empty() {
+        // an explicit timestamp on the command line wins over .timestamp
+        let timestamp = resolve_timestamp(if args.no_timestamp { Some(0) } else { args.timestamp.or(assembly.timestamp) });
+        if let Err(error) = timestamp.and_then(|timestamp| assembly.into_chunk(timestamp).to_file(&args.output_path)) {
+            eprintln!("{}", error);
+            exit(1);
         }
     }
 
-    if args.execute {
-        // todo: support for assembling strings
-        let temp = RefCell::new(Vec::new());
-        let mut vm = VirtualMachine::new(&temp);
-        vm.execute(chunk);
-    } else {
-        chunk.with_header(true).to_file(&args.output_path);
-    }
-
     exit(0);
 }
@@ -16,12 +16,13 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::{fs, process::exit};
+use std::{collections::BTreeMap, fs, process::exit};
 
 use chrono::{TimeZone, Utc};
 use clap::Parser;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-use ccil::{constants::DISASSEMBLER_METADATA_BORDER_LINE, vm::{chunk::Chunk, opcode::OpCodeLookup}};
+use ccil::{asm::operand_names, constants::{DISASSEMBLER_METADATA_BORDER_LINE, header_flag_const}, linker::{ObjectChunk, RelocationKind}, vm::{cfg, hexdump, chunk::{Chunk, ChunkOffset, Instruction, OperandEncoding}, opcode::OpCodeLookup, section::{SymbolTable, load_executable}}};
 
 
 /// ccil bytecode disassembler
@@ -33,7 +34,15 @@ struct Args {
 
     /// Output file
     #[arg(short, long, default_value_t = String::new())]
-    output_path: String
+    output_path: String,
+
+    /// Write the control-flow graph as Graphviz DOT instead of assembly
+    #[arg(long, default_value_t = false, conflicts_with = "hexdump")]
+    cfg: bool,
+
+    /// Write an annotated hex dump of every byte of the file instead of assembly
+    #[arg(long, default_value_t = false)]
+    hexdump: bool
 }
 
 fn main() {
@@ -41,14 +50,20 @@ fn main() {
 
     let args = Args::parse();
     
-    let input_file = match fs::read(&args.input_path) {
+    let input_file = match Vec::<u8>::from_file(&args.input_path) {
         Ok(val) => val,
         Err(error) => {
-            eprintln!("Failed to read input file: {}", error);
+            eprintln!("{}", error);
             exit(1);
         }
     };
 
+    // the hex dump covers whatever is in the file, so it needs no checks
+    if args.hexdump {
+        write_output(&args.output_path, &hexdump::hexdump(&input_file, &opcode_lookup));
+        exit(0);
+    }
+
     // verify header
     if !input_file.verify_possible_header() {
         eprintln!("Header mismatch; file could not be verified as ccil bytecode");
@@ -58,7 +73,8 @@ fn main() {
     let (header_version_major, header_version_minor, header_version_patch) = (input_file[2], input_file[3], input_file[4]);
     let (program_version_major, program_version_minor, program_version_patch) = ccil::version();
     let flags = input_file[5];
-    let bytecode_from_assembly = flags & 0x1 == 1; // 1 if bytecode was assembled, 0 if compiled from source
+    let bytecode_from_assembly = flags & header_flag_const::FROM_ASSEMBLY != 0; // set if bytecode was assembled, unset if compiled from source
+    let encoding = input_file.operand_encoding();
     let date_unix = input_file[6] as i64
                     | (input_file[7] as i64) << 8
                     | (input_file[8] as i64) << 16
@@ -73,18 +89,108 @@ fn main() {
         );
     }
 
-    let chunk = input_file.without_header();
+    // relocatable objects keep their exports and relocations, everything else loads as a program
+    let object = if flags & header_flag_const::RELOCATABLE != 0 {
+        match ObjectChunk::from_chunk(&input_file) {
+            Ok(val) => Some(val),
+            Err(error) => {
+                eprintln!("{}", error);
+                exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let (chunk, string_pool, symbols) = match &object {
+        Some(object) => (object.code.clone(), object.string_pool.clone(), SymbolTable { functions: object.exports.clone(), variables: Vec::new() }),
+        None => match load_executable(&input_file) {
+            Ok(val) => (val.code, val.string_pool, val.symbols),
+            Err(error) => {
+                eprintln!("{}", error);
+                exit(1);
+            }
+        }
+    };
+    // decode everything up front so that branch targets can be labelled
+    let mut instructions = Vec::<Instruction>::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let instruction = match chunk.read_instruction(offset, encoding, &opcode_lookup) {
+            Ok(val) => val,
+            Err(error) => {
+                eprintln!("Error disassembling: {}", error);
+                exit(1);
+            }
+        };
+        offset = instruction.next_offset;
+        instructions.push(instruction);
+    }
+    if args.cfg {
+        write_output(&args.output_path, &cfg::to_dot(&cfg::from_instructions(instructions)));
+        exit(0);
+    }
+
+    // relocations by the offset of the operand they patch
+    let relocations: FxHashMap<ChunkOffset, &RelocationKind> = match &object {
+        Some(object) => object.relocations.iter().map(|relocation| (relocation.offset, &relocation.kind)).collect(),
+        None => FxHashMap::default()
+    };
+    let mut taken_names: FxHashSet<String> = symbols.functions.iter().map(|(name, _)| name.clone())
+        .chain(symbols.variables.iter().map(|(name, _)| name.clone()))
+        .collect();
+
+    // label every branch target that starts an instruction (or is the end of the code),
+    // preferring a function name already given to that offset
+    let boundaries: FxHashSet<ChunkOffset> = instructions.iter().map(|instruction| instruction.offset).chain([chunk.len()]).collect();
+    let mut labels = BTreeMap::<ChunkOffset, String>::new();
+    for (name, function_offset) in &symbols.functions {
+        if boundaries.contains(function_offset) {
+            labels.entry(*function_offset).or_insert_with(|| name.clone());
+        }
+    }
+    for instruction in &instructions {
+        if is_branch(instruction.opcode.symbol) && instruction.args[0] >= 0 && boundaries.contains(&(instruction.args[0] as ChunkOffset)) {
+            let target = instruction.args[0] as ChunkOffset;
+            labels.entry(target).or_insert_with(|| unique_name(format!("label_{}", target), &mut taken_names));
+        }
+    }
+
+    // the string pool, split into null-terminated pieces
+    let mut pieces = Vec::<(ChunkOffset, String, String)>::new();
+    let mut piece_start = 0;
+    while piece_start < string_pool.len() {
+        let piece_end = string_pool[piece_start..].iter().position(|byte| *byte == 0)
+            .map(|end| piece_start + end + 1)
+            .unwrap_or(string_pool.len());
+        let name = unique_name(format!("str_{}", piece_start), &mut taken_names);
+        let directive = render_string_piece(&name, &string_pool[piece_start..piece_end]);
+        pieces.push((piece_start, name, directive));
+        piece_start = piece_end;
+    }
+    if pieces.is_empty() && relocations.values().any(|kind| **kind == RelocationKind::String) {
+        let name = unique_name("str_0".to_string(), &mut taken_names);
+        pieces.push((0, name.clone(), format!(".data {}", name)));
+    }
+
+    // object variables are only known by id, so each gets a generated name
+    let mut variables = symbols.variables.clone();
+    if object.is_some() {
+        let mut ids: Vec<i32> = instructions.iter()
+            .flat_map(|instruction| instruction.args.iter().enumerate().filter_map(|(i, arg)| {
+                (relocations.get(&(instruction.offset + 1 + 4 * i)) == Some(&&RelocationKind::Variable)).then_some(*arg)
+            }))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        variables = ids.into_iter().map(|id| (unique_name(format!("var_{}", id), &mut taken_names), id)).collect();
+    }
+
     let mut assembly = String::new();
 
-    // first we write some metadata
+    // first we write some metadata; the directives let ccila rebuild the same file
     assembly += DISASSEMBLER_METADATA_BORDER_LINE;
     assembly += "// CCIL BYTECODE ASSEMBLY AUTO-GENERATED BY DISASSEMBLER\n";
-    assembly += &format!("// ORIGINAL FILENAME: {}\n", &args.input_path.split('/').last().unwrap());
-    if bytecode_from_assembly {
-        assembly += "// BYTECODE GENERATED FROM: ASSEMBLY\n"
-    } else {
-        assembly += "// BYTECODE GENERATED FROM: SOURCE FILE\n"
-    }
+    assembly += &format!("// ORIGINAL FILENAME: {}\n", &args.input_path.split('/').next_back().unwrap());
     assembly += &format!("// CREATION TIMESTAMP: {}\n", date.format("%Y-%m-%d %H:%M:%S"));
     assembly += &format!(
         "// ORIGINALLY CREATED BY CCIL VER: {}.{}.{}\n",
@@ -99,35 +205,84 @@ fn main() {
         program_version_patch
     );
     assembly += DISASSEMBLER_METADATA_BORDER_LINE;
+    if bytecode_from_assembly {
+        assembly += ".from assembly\n";
+    } else {
+        assembly += ".from source\n";
+    }
+    assembly += &format!(".timestamp {}\n", date_unix);
+    if encoding == OperandEncoding::Compact {
+        assembly += ".encoding compact\n";
+    }
+    if object.is_some() {
+        assembly += ".object\n";
+    } else if flags & header_flag_const::SECTIONED != 0 {
+        assembly += ".sectioned\n";
+    }
     assembly += "\n";
 
-    let mut offset = 0;
-    while offset < chunk.len() {
-        let opcode_byte = chunk[offset];
-        let opcode = match opcode_lookup.from_byte(opcode_byte) {
-            Some(val) => val,
-            None => {
-                eprintln!("Error at offset {}: 0x{:02x} does not match with a valid opcode", offset, opcode_byte);
-                exit(1);
-            }
-        };
-        offset += 1;
+    for (name, id) in &variables {
+        assembly += &format!(".variable {} {}\n", name, id);
+    }
+    let mut externs: Vec<&String> = relocations.values().filter_map(|kind| match kind {
+        RelocationKind::Symbol(name) => Some(name),
+        _ => None
+    }).collect();
+    externs.sort();
+    externs.dedup();
+    for name in &externs {
+        assembly += &format!(".extern {}\n", name);
+    }
+    for (_, _, directive) in &pieces {
+        assembly += directive;
+        assembly += "\n";
+    }
+    if !variables.is_empty() || !externs.is_empty() || !pieces.is_empty() {
+        assembly += "\n";
+    }
 
-        assembly += opcode.symbol;
+    for instruction in &instructions {
+        write_labels(&mut assembly, instruction.offset, &symbols, &labels);
 
-        for _ in 0..opcode.num_params {
-            let arg = chunk.read_arg(offset);
-            assembly.push_str(&format!(" {}", arg));
-            offset += 4;
+        assembly += instruction.opcode.symbol;
+        for (i, arg) in instruction.args.iter().enumerate() {
+            // object code is always fixed-width, so operand i sits at a known offset
+            let operand = match relocations.get(&(instruction.offset + 1 + 4 * i)) {
+                Some(RelocationKind::Symbol(name)) => with_addend(name, *arg as i64),
+                Some(RelocationKind::String) => {
+                    match pieces.iter().rev().find(|(start, _, _)| *start as i64 <= *arg as i64).or(pieces.first()) {
+                        Some((start, name, _)) => with_addend(name, *arg as i64 - *start as i64),
+                        None => arg.to_string()
+                    }
+                },
+                // executables name variables through their symbol table, objects through relocations
+                Some(RelocationKind::Variable) => variable_name(&variables, *arg),
+                None if object.is_none() && matches!(instruction.opcode.symbol, "LOAD" | "STORE" | "INCVAR") && i == 0 => variable_name(&variables, *arg),
+                _ if is_branch(instruction.opcode.symbol) && i == 0 && *arg >= 0 && labels.contains_key(&(*arg as ChunkOffset)) => {
+                    labels[&(*arg as ChunkOffset)].clone()
+                },
+                _ => match operand_names(instruction.opcode.symbol, i).iter().find(|(_, value)| value == arg) {
+                    Some((name, _)) => name.to_string(),
+                    None => arg.to_string()
+                }
+            };
+            assembly += " ";
+            assembly += &operand;
         }
-
         assembly += "\n";
     }
+    write_labels(&mut assembly, chunk.len(), &symbols, &labels);
+
+    write_output(&args.output_path, &assembly);
+    exit(0);
+}
 
-    if args.output_path.is_empty() {
-        print!("{}", assembly)
+/// Prints `text`, or writes it to `output_path` if one was given.
+fn write_output(output_path: &str, text: &str) {
+    if output_path.is_empty() {
+        print!("{}", text)
     } else {
-        match fs::write(args.output_path, assembly) {
+        match fs::write(output_path, text) {
             Ok(_) => {},
             Err(error) => {
                 eprintln!("Failed to write to output file: {}", error);
@@ -135,5 +290,56 @@ fn main() {
             }
         };
     }
-    exit(0);
+}
+
+fn is_branch(symbol: &str) -> bool {
+    return matches!(symbol, "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL");
+}
+
+/// Appends a suffix to `name` until it clashes with nothing already named.
+fn unique_name(mut name: String, taken_names: &mut FxHashSet<String>) -> String {
+    while taken_names.contains(&name) {
+        name.push('_');
+    }
+    taken_names.insert(name.clone());
+    return name;
+}
+
+fn variable_name(variables: &[(String, i32)], id: i32) -> String {
+    match variables.iter().find(|(_, variable_id)| *variable_id == id) {
+        Some((name, _)) => name.clone(),
+        None => id.to_string()
+    }
+}
+
+fn with_addend(name: &str, addend: i64) -> String {
+    match addend {
+        0 => name.to_string(),
+        addend if addend > 0 => format!("{}+{}", name, addend),
+        addend => format!("{}{}", name, addend)
+    }
+}
+
+/// Writes the `.global` names and generated label that belong at `offset`.
+fn write_labels(assembly: &mut String, offset: ChunkOffset, symbols: &SymbolTable, labels: &BTreeMap<ChunkOffset, String>) {
+    let mut exported = false;
+    for (name, _) in symbols.functions.iter().filter(|(_, function_offset)| *function_offset == offset) {
+        *assembly += &format!(".global {}\n", name);
+        exported |= labels.get(&offset) == Some(name);
+    }
+    if let Some(label) = labels.get(&offset).filter(|_| !exported) {
+        *assembly += &format!("{}:\n", label);
+    }
+}
+
+/// Renders one null-terminated piece of the string pool as `.string` if it is
+/// printable text, or as `.data` otherwise.
+fn render_string_piece(name: &str, bytes: &[u8]) -> String {
+    if let Some(text) = bytes.strip_suffix(&[0]).and_then(|text| std::str::from_utf8(text).ok())
+        && text.chars().all(|c| !c.is_control() || c == '\n' || c == '\t') {
+        let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t");
+        return format!(".string {} \"{}\"", name, escaped);
+    }
+    let bytes: Vec<String> = bytes.iter().map(|byte| byte.to_string()).collect();
+    return format!(".data {} {}", name, bytes.join(" "));
 }
@@ -0,0 +1,92 @@
+/*
+bin/linker.rs: The CCIL Bytecode Linker
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{cell::RefCell, process::exit};
+
+use clap::Parser;
+
+use ccil::linker::{ObjectChunk, link};
+use ccil::vm::{VirtualMachine, chunk::{Chunk, resolve_timestamp}};
+
+/// ccil bytecode linker, merges object files into one program
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Paths of ccil object files, in the order they should run
+    #[arg(required = true)]
+    input_paths: Vec<String>,
+
+    /// Flag to execute the linked program rather than writing it
+    #[arg(short, long, default_value_t = false)]
+    execute: bool,
+
+    /// Output file (required if linking to a file, ignored if executing)
+    #[arg(short, long, default_value_t = String::new())]
+    output_path: String,
+
+    /// Header timestamp in UTC Unix seconds (defaults to $SOURCE_DATE_EPOCH, then the current time)
+    #[arg(long, conflicts_with = "no_timestamp")]
+    timestamp: Option<i64>,
+
+    /// Write a zero header timestamp for reproducible output
+    #[arg(long, default_value_t = false)]
+    no_timestamp: bool
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if !args.execute && args.output_path.is_empty() {
+        eprintln!("Output file not specified, run --help for more info");
+        exit(1);
+    }
+
+    let mut modules = Vec::<(String, ObjectChunk)>::new();
+    for path in &args.input_paths {
+        let object = Vec::<u8>::from_file(path).and_then(|chunk| ObjectChunk::from_chunk(&chunk));
+        match object {
+            Ok(val) => modules.push((path.clone(), val)),
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                exit(1);
+            }
+        }
+    }
+
+    let program = match link(&modules) {
+        Ok(val) => val,
+        Err(error) => {
+            eprintln!("Error linking: {}", error);
+            exit(1);
+        }
+    };
+
+    if args.execute {
+        let string_pool = RefCell::new(program.string_pool);
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.execute(program.code);
+    } else {
+        let timestamp = resolve_timestamp(if args.no_timestamp { Some(0) } else { args.timestamp });
+        if let Err(error) = timestamp.and_then(|timestamp| program.to_executable().to_chunk(0, timestamp).to_file(&args.output_path)) {
+            eprintln!("{}", error);
+            exit(1);
+        }
+    }
+
+    exit(0);
+}
@@ -0,0 +1,73 @@
+/*
+bin/optimizer.rs: The CCIL peephole optimizer
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::process::exit;
+
+use clap::Parser;
+
+use ccil::constants::header_flag_const;
+use ccil::linker::ObjectChunk;
+use ccil::optimizer::{optimize_executable, optimize_object};
+use ccil::vm::{chunk::{Chunk, ChunkError}, opcode::OpCodeLookup, section::load_executable};
+
+/// ccil bytecode optimizer, rewrites programs and objects with peephole optimizations
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Path of ccil bytecode or object file. Executables whose constants hold
+    /// the address of code that would move are written out unchanged
+    input_path: String,
+
+    /// Output file, keeping the input's header flags and timestamp
+    #[arg(short, long)]
+    output_path: String
+}
+
+fn optimize_file(file: &Vec<u8>, lookup: &OpCodeLookup) -> Result<Vec<u8>, ChunkError> {
+    let flags = file.header_flags();
+    let timestamp = match file.verify_possible_header() {
+        true => u32::from_le_bytes([file[6], file[7], file[8], file[9]]) as i64,
+        false => 0
+    };
+
+    if flags & header_flag_const::RELOCATABLE != 0 {
+        let object = optimize_object(&ObjectChunk::from_chunk(file)?, lookup)?;
+        return Ok(object.to_chunk(flags & header_flag_const::FROM_ASSEMBLY, timestamp));
+    }
+
+    let mut executable = optimize_executable(&load_executable(file)?, lookup)?;
+    if flags & header_flag_const::SECTIONED != 0 {
+        return Ok(executable.to_chunk(flags & header_flag_const::FROM_ASSEMBLY, timestamp));
+    }
+    if file.verify_possible_header() {
+        return Ok(executable.code.with_header(flags, timestamp));
+    }
+    return Ok(executable.code);
+}
+
+fn main() {
+    let args = Args::parse();
+    let lookup = OpCodeLookup::new();
+
+    let optimized = Vec::<u8>::from_file(&args.input_path).and_then(|file| optimize_file(&file, &lookup));
+    let result = optimized.and_then(|chunk| chunk.to_file(&args.output_path));
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        exit(1);
+    }
+}
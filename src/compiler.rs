@@ -20,18 +20,26 @@ use std::cell::RefCell;
 
 use rustc_hash::FxHashMap;
 
-use crate::{constants::type_id_const, parser::expr::Expr, vm::opcode::OpCodeLookup};
+use crate::{constants::{GENERIC_COMPILE_ERROR, type_id_const}, linker::{ObjectChunk, Relocation, RelocationKind}, optimizer, parser::expr::Expr, vm::{chunk::{Chunk, ChunkError, OperandEncoding}, opcode::OpCodeLookup, section::SymbolTable}};
 
 pub mod emitters;
+pub mod registers;
 pub mod rules;
 
 pub type VariableId = i32;
 pub type CCILTypeId = i32; // disambiguate from std::any::TypeId
 
+/// Output depends only on the input: variable ids and string pool offsets are
+/// handed out in the order names and literals are first compiled, and the hash
+/// maps are only used for lookups, never iterated into the output.
 pub struct Compiler<'a> {
     lookup: OpCodeLookup<'a>,
     variables: RefCell<FxHashMap<String, (VariableId, CCILTypeId)>>,
     string_map: RefCell<FxHashMap<String, usize>>,
+    operand_encoding: OperandEncoding,
+    optimize: bool,
+    // whether each CONST emitted by the last compile() holds a string pool offset, in emission order
+    constant_is_string: RefCell<Vec<bool>>,
     pub string_pool: RefCell<Vec<u8>>
 }
 
@@ -41,11 +49,35 @@ impl Compiler<'_> {
             lookup: OpCodeLookup::new(),
             variables: RefCell::new(FxHashMap::default()),
             string_map: RefCell::new(FxHashMap::default()),
+            operand_encoding: OperandEncoding::Fixed,
+            optimize: false,
+            constant_is_string: RefCell::new(Vec::new()),
             string_pool: RefCell::new(Vec::new())
         }
     }
 
+    /// Sets the operand encoding used by the emitters.
+    pub fn set_operand_encoding(&mut self, encoding: OperandEncoding) {
+        self.operand_encoding = encoding;
+    }
+
+    /// Sets whether compiled code is run through the peephole optimizer.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
     pub fn compile(&self, expressions: &Vec<Expr>) -> Vec<u8> {
+        let code = self.compile_unoptimized(expressions);
+        if !self.optimize {
+            return code;
+        }
+        return optimizer::optimize(&code, self.operand_encoding, &[], &[], &self.lookup)
+            .unwrap_or_else(|err| panic!("{}: {}", GENERIC_COMPILE_ERROR, err))
+            .code;
+    }
+
+    fn compile_unoptimized(&self, expressions: &Vec<Expr>) -> Vec<u8> {
+        self.constant_is_string.borrow_mut().clear();
         let mut retval = Vec::<u8>::new();
         for expression in expressions {
             let (mut compiled, _) = self.compile_one(expression);
@@ -54,6 +86,62 @@ impl Compiler<'_> {
         return retval;
     }
 
+    /// Compiles a module into a relocatable object that exports `module_name`
+    /// at its first instruction. Strings and variables are private to the module,
+    /// and operands must be fixed-width so that the linker can patch them.
+    pub fn compile_object(&self, expressions: &Vec<Expr>, module_name: &str) -> Result<ObjectChunk, ChunkError> {
+        if self.operand_encoding != OperandEncoding::Fixed {
+            return Err(ChunkError::CompactObject);
+        }
+
+        let code = self.compile_unoptimized(expressions);
+        let mut relocations = Vec::<Relocation>::new();
+
+        // Fragments are always appended in the order they were compiled, so the
+        // nth CONST in the output is the nth literal compiled.
+        let constant_is_string = self.constant_is_string.borrow();
+        let mut constant_index = 0;
+        let mut offset = 0;
+        while offset < code.len() {
+            let instruction = code.read_instruction(offset, self.operand_encoding, &self.lookup)
+                .unwrap_or_else(|err| panic!("{}: {}", GENERIC_COMPILE_ERROR, err));
+            match instruction.opcode.symbol {
+                "CONST" => {
+                    if constant_is_string[constant_index] {
+                        relocations.push(Relocation { offset: offset + 1, kind: RelocationKind::String });
+                    }
+                    constant_index += 1;
+                },
+                "LOAD" | "STORE" | "INCVAR" => relocations.push(Relocation { offset: offset + 1, kind: RelocationKind::Variable }),
+                "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL" => relocations.push(Relocation { offset: offset + 1, kind: RelocationKind::Code }),
+                _ => {}
+            }
+            offset = instruction.next_offset;
+        }
+
+        let object = ObjectChunk {
+            code,
+            string_pool: self.string_pool.borrow().clone(),
+            exports: vec![(module_name.to_string(), 0)],
+            relocations
+        };
+        if !self.optimize {
+            return Ok(object);
+        }
+        return Ok(optimizer::optimize_object(&object, &self.lookup)
+            .unwrap_or_else(|err| panic!("{}: {}", GENERIC_COMPILE_ERROR, err)));
+    }
+
+    /// Names every global variable compiled so far, ordered by id.
+    /// Functions are listed once the compiler can emit them.
+    pub fn symbol_table(&self) -> SymbolTable {
+        let mut variables: Vec<(String, VariableId)> = self.variables.borrow().iter()
+            .map(|(name, (id, _))| (name.clone(), *id))
+            .collect();
+        variables.sort_by_key(|(_, id)| *id);
+        SymbolTable { functions: Vec::new(), variables }
+    }
+
     fn compile_one(&self, expression: &Expr) -> (Vec<u8>, CCILTypeId) {
         let mut retval = Vec::<u8>::new();
         use Expr::*;
@@ -82,9 +170,9 @@ impl Compiler<'_> {
         }
     }
 
-    fn set_inferred_type(&self, var_name: &String, type_id: CCILTypeId) {
+    fn set_inferred_type(&self, var_name: &str, type_id: CCILTypeId) {
         let mut borrowed_variables = self.variables.borrow_mut();
-        borrowed_variables.entry(var_name.clone()).and_modify(|(_, old_id)| *old_id = type_id);
+        borrowed_variables.entry(var_name.to_string()).and_modify(|(_, old_id)| *old_id = type_id);
     }
 
     // fn to_variable_value(&self, expression: &Expr) -> VariableValue {
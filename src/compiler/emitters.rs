@@ -9,7 +9,7 @@ impl Compiler<'_> {
     pub fn emit_constant(&self, value: Argument) -> Vec<u8> {
         let const_opcode = self.lookup.from_symbol("CONST").unwrap();
         let mut retval = vec![const_opcode.byte];
-        retval.write_arg(value);
+        retval.write_operand(value, self.operand_encoding);
 
         return retval;
     }
@@ -17,8 +17,8 @@ impl Compiler<'_> {
     pub fn emit_assignment(&self, var_id: Argument, type_id: Argument) -> Vec<u8> {
         let store_opcode = self.lookup.from_symbol("STORE").unwrap();
         let mut retval = vec![store_opcode.byte];
-        retval.write_arg(var_id);
-        retval.write_arg(type_id);
+        retval.write_operand(var_id, self.operand_encoding);
+        retval.write_operand(type_id, self.operand_encoding);
 
         return retval;
     }
@@ -26,7 +26,7 @@ impl Compiler<'_> {
     pub fn emit_load(&self, var_id: Argument) -> Vec<u8> {
         let load_opcode = self.lookup.from_symbol("LOAD").unwrap();
         let mut retval = vec![load_opcode.byte];
-        retval.write_arg(var_id);
+        retval.write_operand(var_id, self.operand_encoding);
 
         return retval;
     }
@@ -34,7 +34,7 @@ impl Compiler<'_> {
     pub fn emit_write(&self, fileno: Argument) -> Vec<u8> {
         let writes_opcode = self.lookup.from_symbol("WRITE").unwrap();
         let mut retval = vec![writes_opcode.byte];
-        retval.write_arg(fileno);
+        retval.write_operand(fileno, self.operand_encoding);
 
         return retval;
     }
@@ -42,7 +42,7 @@ impl Compiler<'_> {
     pub fn emit_writes(&self, fileno: Argument) -> Vec<u8> {
         let writes_opcode = self.lookup.from_symbol("WRITES").unwrap();
         let mut retval = vec![writes_opcode.byte];
-        retval.write_arg(fileno);
+        retval.write_operand(fileno, self.operand_encoding);
 
         return retval;
     }
@@ -0,0 +1,152 @@
+/*
+compiler/registers.rs: Compiles expressions for the register machine
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::compiler::{CCILTypeId, Compiler, rules::arithmetic_instruction};
+use crate::constants::{fileno_const, type_id_const};
+use crate::parser::{expr::Expr, token::Token};
+use crate::vm::register::{Register, RegisterOp, RegisterProgram};
+
+// temporaries are numbered from this bit until every variable has a register
+const TEMPORARY: Register = 1 << 31;
+
+/// Register code being generated. Temporaries are allocated like a stack, and
+/// each statement starts with all of them free.
+struct RegisterCode {
+    ops: Vec<RegisterOp>,
+    next_temporary: Register,
+    temporaries: Register
+}
+
+impl RegisterCode {
+    fn temporary(&mut self) -> Register {
+        let register = TEMPORARY | self.next_temporary;
+        self.next_temporary += 1;
+        self.temporaries = self.temporaries.max(self.next_temporary);
+        return register;
+    }
+}
+
+impl Compiler<'_> {
+    /// Compiles expressions for the register machine, which accepts the same
+    /// expressions as `compile`. Every variable gets a register of its own, so
+    /// reading one costs no instruction.
+    pub fn compile_registers(&self, expressions: &Vec<Expr>) -> RegisterProgram {
+        let mut code = RegisterCode { ops: Vec::new(), next_temporary: 0, temporaries: 0 };
+        for expression in expressions {
+            code.next_temporary = 0;
+            self.compile_register_one(expression, None, &mut code);
+        }
+
+        let variables = self.variables.borrow();
+        let mut variable_types = vec![type_id_const::UNKNOWN; variables.len()];
+        for (id, type_id) in variables.values() {
+            variable_types[*id as usize] = *type_id;
+        }
+        let first_temporary = variables.len() as Register;
+        let ops = code.ops.into_iter()
+            .map(|op| op.map_registers(|register| match register & TEMPORARY {
+                0 => register,
+                _ => first_temporary + (register & !TEMPORARY)
+            }))
+            .collect();
+        return RegisterProgram { ops, registers: variables.len() + code.temporaries as usize, variable_types };
+    }
+
+    /// Compiles an expression, leaving its value in `target` if one is given,
+    /// and returns the register holding the value and its type.
+    fn compile_register_one(&self, expression: &Expr, target: Option<Register>, code: &mut RegisterCode) -> (Register, CCILTypeId) {
+        use Expr::*;
+        return match expression {
+            Literal(token) => {
+                let (value, type_id) = self.literal_value(token);
+                let dst = target.unwrap_or_else(|| code.temporary());
+                code.ops.push(RegisterOp::Const(dst, value));
+                (dst, type_id)
+            },
+            Variable(token) => {
+                let var_name = match token.get_var_name() {
+                    Some(val) => val,
+                    None => panic!("{}", "Attempted illegal assignment")
+                };
+                let (var_id, type_id) = self.get_or_insert(var_name);
+                if type_id == type_id_const::UNKNOWN {
+                    panic!("Attempted to access valueless variable");
+                }
+                match target {
+                    Some(dst) if dst != var_id as Register => {
+                        code.ops.push(RegisterOp::Move(dst, var_id as Register));
+                        (dst, type_id)
+                    },
+                    _ => (var_id as Register, type_id)
+                }
+            },
+            Binary(Token::Equals, left, right) => {
+                let var_name = left.get_token().get_var_name().unwrap();
+                let (var_id, _) = self.get_or_insert(var_name);
+                let (_, type_id) = self.compile_register_one(right, Some(var_id as Register), code);
+                self.set_inferred_type(var_name, type_id);
+                (var_id as Register, type_id_const::UNKNOWN) // assignments have no value
+            },
+            Binary(token, left, right) => {
+                let first_free = code.next_temporary;
+                let (a, left_type_id) = self.compile_register_one(left, None, code);
+
+                // adding or subtracting a number needs no register for it
+                let immediate = match (token, &**right) {
+                    (Token::Plus, Literal(Token::Number(value))) => Some(*value),
+                    (Token::Minus, Literal(Token::Number(value))) => value.checked_neg(),
+                    _ => None
+                };
+                if let Some(value) = immediate {
+                    let (_, type_id) = arithmetic_instruction(token, left_type_id, type_id_const::NUMBER);
+                    code.next_temporary = first_free;
+                    let dst = target.unwrap_or_else(|| code.temporary());
+                    code.ops.push(RegisterOp::AddImm(dst, a, value));
+                    return (dst, type_id);
+                }
+
+                let (b, right_type_id) = self.compile_register_one(right, None, code);
+                let (instr, type_id) = arithmetic_instruction(token, left_type_id, right_type_id);
+                // operands are read before the result is written, so it may reuse their registers
+                code.next_temporary = first_free;
+                let dst = target.unwrap_or_else(|| code.temporary());
+                let op = match instr {
+                    "ADD" => RegisterOp::Add(dst, a, b),
+                    "SUB" => RegisterOp::Sub(dst, a, b),
+                    "MUL" => RegisterOp::Mul(dst, a, b),
+                    "DIV" => RegisterOp::Div(dst, a, b),
+                    "MOD" => RegisterOp::Mod(dst, a, b),
+                    _ => unreachable!()
+                };
+                code.ops.push(op);
+                (dst, type_id)
+            },
+            PrintStatement(expr) => {
+                let first_free = code.next_temporary;
+                let (src, type_id) = self.compile_register_one(expr, None, code);
+                code.ops.push(match type_id {
+                    type_id_const::STRING => RegisterOp::WriteS(src, fileno_const::STDOUT),
+                    _ => RegisterOp::Write(src, fileno_const::STDOUT)
+                });
+                code.next_temporary = first_free;
+                (src, type_id_const::UNKNOWN)
+            },
+            _ => todo!()
+        };
+    }
+}
@@ -1,8 +1,15 @@
-use crate::{compiler::Compiler, constants::{GENERIC_COMPILE_ERROR, fileno_const, type_id_const}, parser::{expr::Expr, token::Token}, vm::opcode::Argument};
+use crate::{compiler::{CCILTypeId, Compiler}, constants::{GENERIC_COMPILE_ERROR, fileno_const, type_id_const}, parser::{expr::Expr, token::Token}, vm::opcode::Argument};
 
 impl Compiler<'_> {
     pub fn compile_literal(&self, token: &Token) -> (Vec<u8>, Argument) {
-        let (val, type_id) = match token {
+        let (val, type_id) = self.literal_value(token);
+        self.constant_is_string.borrow_mut().push(type_id == type_id_const::STRING);
+        (self.emit_constant(val), type_id)
+    }
+
+    /// The constant and type id of a literal, adding strings to the string pool.
+    pub fn literal_value(&self, token: &Token) -> (Argument, CCILTypeId) {
+        return match token {
             Token::Number(val) => (*val, type_id_const::NUMBER),
             Token::String(val) => {
                 let string_id = self.find_or_insert_string(val);
@@ -19,8 +26,6 @@ impl Compiler<'_> {
             Token::Null => (0, type_id_const::NULL),
             _ => panic!("{}", GENERIC_COMPILE_ERROR)
         };
-
-        (self.emit_constant(val), type_id)
     }
 
     pub fn compile_binary(&self, token: &Token, left: &Expr, right: &Expr) -> (Vec<u8>, Argument) {
@@ -46,48 +51,7 @@ impl Compiler<'_> {
                 let (mut compile_right, right_type_id) = self.compile_one(right);
                 retval.append(&mut compile_left);
                 retval.append(&mut compile_right);
-                let (instr, type_id) = match token {
-                    Plus => {
-                        match (left_type_id, right_type_id) {
-                            (type_id_const::NUMBER, type_id_const::NUMBER) => {
-                                ("ADD", type_id_const::NUMBER)
-                            },
-                            _ => todo!() // e.g. string plus number or string plus string
-                        }
-                    }
-                    Minus => {
-                        if (left_type_id, right_type_id) == (type_id_const::NUMBER, type_id_const::NUMBER) {
-                            ("SUB", type_id_const::NUMBER)
-                        } else {
-                            panic!("{}", GENERIC_COMPILE_ERROR)
-                        }
-                    }
-                    Star => {
-                        match (left_type_id, right_type_id) {
-                            (type_id_const::NUMBER, type_id_const::NUMBER) => {
-                                ("MUL", type_id_const::NUMBER)
-                            },
-                            _ => todo!() // e.g. string times number
-                        }
-                    }
-                    Slash => {
-                        match (left_type_id, right_type_id) {
-                            (type_id_const::NUMBER, type_id_const::NUMBER) => {
-                                ("DIV", type_id_const::NUMBER)
-                            },
-                            _ => todo!() // float division
-                        }
-                    }
-                    Percent => {
-                        if (left_type_id, right_type_id) == (type_id_const::NUMBER, type_id_const::NUMBER) {
-                            ("MOD", type_id_const::NUMBER)
-                        } else {
-                            panic!("{}", GENERIC_COMPILE_ERROR)
-                        }
-                    }
-                    // TODO: Bitwise, boolean, comparison
-                    _ => panic!("{}", GENERIC_COMPILE_ERROR)
-                };
+                let (instr, type_id) = arithmetic_instruction(token, left_type_id, right_type_id);
                 let mut instr_op = self.emit_instr(instr);
                 retval.append(&mut instr_op);
                 (retval, type_id)
@@ -122,3 +86,51 @@ impl Compiler<'_> {
         return (self.emit_load(var_id), type_id);
     }
 }
+
+/// The stack instruction for an arithmetic operator on operands of the given
+/// types, and the type of its result.
+pub fn arithmetic_instruction(token: &Token, left_type_id: CCILTypeId, right_type_id: CCILTypeId) -> (&'static str, CCILTypeId) {
+    use Token::*;
+    return match token {
+        Plus => {
+            match (left_type_id, right_type_id) {
+                (type_id_const::NUMBER, type_id_const::NUMBER) => {
+                    ("ADD", type_id_const::NUMBER)
+                },
+                _ => todo!() // e.g. string plus number or string plus string
+            }
+        }
+        Minus => {
+            if (left_type_id, right_type_id) == (type_id_const::NUMBER, type_id_const::NUMBER) {
+                ("SUB", type_id_const::NUMBER)
+            } else {
+                panic!("{}", GENERIC_COMPILE_ERROR)
+            }
+        }
+        Star => {
+            match (left_type_id, right_type_id) {
+                (type_id_const::NUMBER, type_id_const::NUMBER) => {
+                    ("MUL", type_id_const::NUMBER)
+                },
+                _ => todo!() // e.g. string times number
+            }
+        }
+        Slash => {
+            match (left_type_id, right_type_id) {
+                (type_id_const::NUMBER, type_id_const::NUMBER) => {
+                    ("DIV", type_id_const::NUMBER)
+                },
+                _ => todo!() // float division
+            }
+        }
+        Percent => {
+            if (left_type_id, right_type_id) == (type_id_const::NUMBER, type_id_const::NUMBER) {
+                ("MOD", type_id_const::NUMBER)
+            } else {
+                panic!("{}", GENERIC_COMPILE_ERROR)
+            }
+        }
+        // TODO: Bitwise, boolean, comparison
+        _ => panic!("{}", GENERIC_COMPILE_ERROR)
+    };
+}
@@ -22,6 +22,15 @@ pub const CCIL_MAGIC_BYTE_0: u8 = 0xCC;
 pub const CCIL_MAGIC_BYTE_1: u8 = 0x17;
 
 pub const BYTECODE_HEADER_SIZE: usize = 16;
+pub const BYTECODE_HEADER_FLAGS_OFFSET: usize = 5;
+
+// bits of the header flags byte
+pub mod header_flag_const {
+    pub const FROM_ASSEMBLY: u8 = 0b01;
+    pub const COMPACT_OPERANDS: u8 = 0b10; // operands are zigzag LEB128 varints
+    pub const SECTIONED: u8 = 0b100; // body is a list of sections rather than bare code
+    pub const RELOCATABLE: u8 = 0b1000; // unlinked object chunk
+}
 
 pub const DISASSEMBLER_METADATA_BORDER_LINE: &str = "// -----------------------------------------------------------\n";
 
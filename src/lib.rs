@@ -16,18 +16,25 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::sync::OnceLock;
+
 use clap::Parser as ArgParser;
 
+use crate::vm::arithmetic::ArithmeticMode;
+
+pub mod asm;
 pub mod parser;
 pub mod compiler;
+pub mod linker;
+pub mod optimizer;
 pub mod vm;
 pub mod constants;
 
 pub fn version() -> (u8, u8, u8) {
     (
-        u8::from_str_radix(env!("CARGO_PKG_VERSION_MAJOR"), 10).unwrap(),
-        u8::from_str_radix(env!("CARGO_PKG_VERSION_MINOR"), 10).unwrap(),
-        u8::from_str_radix(env!("CARGO_PKG_VERSION_PATCH"), 10).unwrap()
+        env!("CARGO_PKG_VERSION_MAJOR").parse::<u8>().unwrap(),
+        env!("CARGO_PKG_VERSION_MINOR").parse::<u8>().unwrap(),
+        env!("CARGO_PKG_VERSION_PATCH").parse::<u8>().unwrap()
     )
 }
 
@@ -41,14 +48,57 @@ pub struct Args {
 
     /// Whether or not to print compiler information
     #[arg(short, long, default_value_t = false)]
-    pub debug: bool
+    pub debug: bool,
+
+    /// Compile to a relocatable object file for ccill instead of running
+    #[arg(long, default_value_t = false)]
+    pub object: bool,
+
+    /// Fold constant expressions and run the peephole optimizer over compiled bytecode
+    #[arg(short = 'O', long, default_value_t = false)]
+    pub optimize: bool,
+
+    /// Virtual machine that runs the program; the stack machine is the reference
+    #[arg(long, value_enum, default_value_t = Engine::Stack)]
+    pub engine: Engine,
+
+    /// What integer arithmetic does on overflow: fail with a runtime error, or wrap around
+    #[arg(long, value_enum, default_value_t = ArithmeticMode::Checked)]
+    pub arithmetic: ArithmeticMode,
+
+    /// Write compiled bytecode to this file instead of running it
+    #[arg(short, long, default_value_t = String::new())]
+    pub output_path: String,
+
+    /// Header timestamp in UTC Unix seconds (defaults to $SOURCE_DATE_EPOCH, then the current time)
+    #[arg(long, conflicts_with = "no_timestamp")]
+    pub timestamp: Option<i64>,
+
+    /// Write a zero header timestamp for reproducible output
+    #[arg(long, default_value_t = false)]
+    pub no_timestamp: bool
+}
+
+/// The virtual machines that `ccil` can run programs on.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// The stack machine, which runs CCIL bytecode
+    Stack,
+    /// The register machine, which runs register code compiled in memory
+    Register
+}
+
+/// Whether `--debug` was passed, parsed from the command line once.
+pub fn debug_enabled() -> bool {
+    static DEBUG: OnceLock<bool> = OnceLock::new();
+    // other binaries (e.g. ccila) have their own args, which never enable debug output
+    return *DEBUG.get_or_init(|| <Args as ArgParser>::try_parse().is_ok_and(|args| args.debug));
 }
 
 #[macro_export]
 macro_rules! dprint {
     ($($arg:tt)*) => {
-        let args = <$crate::Args as clap::Parser>::parse();
-        if args.debug {
+        if $crate::debug_enabled() {
             print!($($arg)*);
         }
     };
@@ -57,8 +107,7 @@ macro_rules! dprint {
 #[macro_export]
 macro_rules! dprintln {
     ($($arg:tt)*) => {
-        let args = <$crate::Args as clap::Parser>::parse();
-        if args.debug {
+        if $crate::debug_enabled() {
             println!($($arg)*);
         }
     };
@@ -0,0 +1,281 @@
+/*
+linker.rs: The CCIL Bytecode Linker
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{error::Error, fmt};
+
+use rustc_hash::FxHashMap;
+
+use crate::constants::header_flag_const;
+use crate::vm::chunk::{Chunk, ChunkError, ChunkOffset};
+use crate::vm::opcode::Argument;
+use crate::vm::chunk::OperandEncoding;
+use crate::vm::section::{Executable, Section, SymbolTable, decode_sections, encode_sections, find_section, read_name, section_id_const, write_name};
+
+/// What the operand at a relocation's offset refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// An offset into this object's code (`CALL`/`JUMP`/`IFZ`/`IFNZ` targets).
+    Code,
+    /// An offset into this object's string pool.
+    String,
+    /// A variable id private to this object.
+    Variable,
+    /// The address of a symbol exported by another object, plus the operand as an addend.
+    Symbol(String)
+}
+
+/// A fixed-width operand in an object's code that must be patched when linking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: ChunkOffset,
+    pub kind: RelocationKind
+}
+
+// relocation kind ids, as stored in the relocation section
+pub mod relocation_id_const {
+    pub const CODE: u8 = 0x00;
+    pub const STRING: u8 = 0x01;
+    pub const VARIABLE: u8 = 0x02;
+    pub const SYMBOL: u8 = 0x03;
+}
+
+/// A separately compiled or assembled module. Object code always uses fixed-width
+/// operands so that relocations can be patched in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectChunk {
+    pub code: Vec<u8>,
+    pub string_pool: Vec<u8>,
+    pub exports: Vec<(String, ChunkOffset)>,
+    pub relocations: Vec<Relocation>
+}
+
+/// The output of linking: one program with a merged string pool.
+pub struct LinkedProgram {
+    pub code: Vec<u8>,
+    pub string_pool: Vec<u8>,
+    pub symbols: FxHashMap<String, ChunkOffset>
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    /// Two modules export the same name.
+    DuplicateSymbol { name: String, first: String, second: String },
+    /// A module imports a name nobody exports.
+    UndefinedSymbol { name: String, module: String },
+    /// A relocation points past the end of its module's code.
+    BadRelocation { module: String, offset: ChunkOffset },
+    /// Relocating the operand at `offset` takes it out of the range of an argument.
+    Overflow { module: String, offset: ChunkOffset },
+    /// A file could not be decoded as an object chunk.
+    Chunk { module: String, source: ChunkError }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol { name, first, second } => write!(
+                f,
+                "Symbol {} is exported by both {} and {}", name, first, second
+            ),
+            LinkError::UndefinedSymbol { name, module } => write!(f, "Undefined symbol {} referenced in {}", name, module),
+            LinkError::BadRelocation { module, offset } => write!(f, "Relocation at offset {} is out of bounds in {}", offset, module),
+            LinkError::Overflow { module, offset } => write!(f, "Relocated operand at offset {} overflows in {}", offset, module),
+            LinkError::Chunk { module, source } => write!(f, "{}: {}", module, source),
+        }
+    }
+}
+
+impl Error for LinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LinkError::Chunk { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+impl ObjectChunk {
+    /// Serializes to a headered, sectioned, relocatable chunk.
+    pub fn to_chunk(&self, flags: u8, timestamp: i64) -> Vec<u8> {
+        let mut exports = Vec::<u8>::new();
+        for (name, offset) in &self.exports {
+            write_name(&mut exports, name);
+            exports.write_arg(*offset as Argument);
+        }
+
+        let mut relocations = Vec::<u8>::new();
+        for relocation in &self.relocations {
+            match &relocation.kind {
+                RelocationKind::Code => relocations.write_byte(relocation_id_const::CODE),
+                RelocationKind::String => relocations.write_byte(relocation_id_const::STRING),
+                RelocationKind::Variable => relocations.write_byte(relocation_id_const::VARIABLE),
+                RelocationKind::Symbol(_) => relocations.write_byte(relocation_id_const::SYMBOL)
+            }
+            relocations.write_arg(relocation.offset as Argument);
+            if let RelocationKind::Symbol(name) = &relocation.kind {
+                write_name(&mut relocations, name);
+            }
+        }
+
+        let mut chunk = encode_sections(&[
+            Section { id: section_id_const::CODE, data: self.code.clone() },
+            Section { id: section_id_const::STRINGS, data: self.string_pool.clone() },
+            Section { id: section_id_const::EXPORTS, data: exports },
+            Section { id: section_id_const::RELOCATIONS, data: relocations }
+        ]);
+        return chunk.with_header(flags | header_flag_const::SECTIONED | header_flag_const::RELOCATABLE, timestamp);
+    }
+
+    /// Parses a chunk written by `to_chunk`.
+    pub fn from_chunk(chunk: &Vec<u8>) -> Result<Self, ChunkError> {
+        let flags = chunk.header_flags();
+        if flags & header_flag_const::RELOCATABLE == 0 || flags & header_flag_const::COMPACT_OPERANDS != 0 {
+            return Err(ChunkError::NotRelocatable);
+        }
+        let sections = decode_sections(&chunk.without_header()?)?;
+
+        let mut object = ObjectChunk {
+            code: find_section(&sections, section_id_const::CODE).cloned().unwrap_or_default(),
+            string_pool: find_section(&sections, section_id_const::STRINGS).cloned().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if let Some(exports) = find_section(&sections, section_id_const::EXPORTS) {
+            let mut offset = 0;
+            while offset < exports.len() {
+                let (name, next) = read_name(exports, section_id_const::EXPORTS, offset)?;
+                let Ok(address) = ChunkOffset::try_from(exports.read_arg(next)?) else {
+                    return Err(ChunkError::MalformedSection { section: section_id_const::EXPORTS, offset: next });
+                };
+                object.exports.push((name, address));
+                offset = next + 4;
+            }
+        }
+
+        if let Some(relocations) = find_section(&sections, section_id_const::RELOCATIONS) {
+            let mut offset = 0;
+            while offset < relocations.len() {
+                let kind_id = relocations[offset];
+                let Ok(target) = ChunkOffset::try_from(relocations.read_arg(offset + 1)?) else {
+                    return Err(ChunkError::MalformedSection { section: section_id_const::RELOCATIONS, offset });
+                };
+                offset += 5;
+                let kind = match kind_id {
+                    relocation_id_const::CODE => RelocationKind::Code,
+                    relocation_id_const::STRING => RelocationKind::String,
+                    relocation_id_const::VARIABLE => RelocationKind::Variable,
+                    relocation_id_const::SYMBOL => {
+                        let (name, next) = read_name(relocations, section_id_const::RELOCATIONS, offset)?;
+                        offset = next;
+                        RelocationKind::Symbol(name)
+                    },
+                    _ => return Err(ChunkError::MalformedSection { section: section_id_const::RELOCATIONS, offset: offset - 5 })
+                };
+                object.relocations.push(Relocation { offset: target, kind });
+            }
+        }
+
+        return Ok(object);
+    }
+}
+
+/// Links named modules, in order, into one program. Module code is laid out
+/// back to back, so execution starts at the first module and falls through
+/// into the next.
+pub fn link(modules: &[(String, ObjectChunk)]) -> Result<LinkedProgram, LinkError> {
+    let mut code = Vec::<u8>::new();
+    let mut string_pool = Vec::<u8>::new();
+    let mut symbols = FxHashMap::<String, ChunkOffset>::default();
+    let mut symbol_owners = FxHashMap::<String, String>::default();
+
+    // first pass: lay out modules and collect exports
+    let mut bases = Vec::<(ChunkOffset, usize, Argument)>::new();
+    let mut variable_base: Argument = 0;
+    for (module, object) in modules {
+        bases.push((code.len(), string_pool.len(), variable_base));
+
+        for (name, offset) in &object.exports {
+            if let Some(first) = symbol_owners.get(name) {
+                return Err(LinkError::DuplicateSymbol { name: name.clone(), first: first.clone(), second: module.clone() });
+            }
+            symbols.insert(name.clone(), code.len() + offset);
+            symbol_owners.insert(name.clone(), module.clone());
+        }
+
+        // each module gets its own block of variable ids
+        let mut variable_count = 0;
+        for relocation in &object.relocations {
+            if relocation.kind == RelocationKind::Variable {
+                let id = object.code.read_arg(relocation.offset)
+                    .map_err(|source| LinkError::Chunk { module: module.clone(), source })?;
+                // ids must still fit once this module's block is placed after the others
+                let Some(count) = id.checked_add(1).filter(|count| variable_base.checked_add(*count).is_some()) else {
+                    return Err(LinkError::Overflow { module: module.clone(), offset: relocation.offset });
+                };
+                variable_count = variable_count.max(count);
+            }
+        }
+        variable_base += variable_count;
+
+        code.extend_from_slice(&object.code);
+        string_pool.extend_from_slice(&object.string_pool);
+    }
+
+    // second pass: patch operands
+    for ((module, object), (code_base, string_base, variable_base)) in modules.iter().zip(bases) {
+        for relocation in &object.relocations {
+            if relocation.offset.checked_add(4).is_none_or(|end| end > object.code.len()) {
+                return Err(LinkError::BadRelocation { module: module.clone(), offset: relocation.offset });
+            }
+            let position = code_base + relocation.offset;
+            let operand = code.read_arg(position)
+                .map_err(|source| LinkError::Chunk { module: module.clone(), source })?;
+            let base = match &relocation.kind {
+                RelocationKind::Code => code_base,
+                RelocationKind::String => string_base,
+                RelocationKind::Variable => variable_base as usize,
+                RelocationKind::Symbol(name) => match symbols.get(name) {
+                    Some(address) => *address,
+                    None => return Err(LinkError::UndefinedSymbol { name: name.clone(), module: module.clone() })
+                }
+            };
+            let Some(patched) = Argument::try_from(base).ok().and_then(|base| operand.checked_add(base)) else {
+                return Err(LinkError::Overflow { module: module.clone(), offset: relocation.offset });
+            };
+            let mut encoded = Vec::<u8>::new();
+            encoded.write_arg(patched);
+            code[position..position + 4].copy_from_slice(&encoded);
+        }
+    }
+
+    return Ok(LinkedProgram { code, string_pool, symbols });
+}
+
+impl LinkedProgram {
+    /// Converts to an executable whose symbol table lists every export as a function.
+    pub fn to_executable(self) -> Executable {
+        let mut functions: Vec<(String, ChunkOffset)> = self.symbols.into_iter().collect();
+        functions.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        Executable {
+            code: self.code,
+            string_pool: self.string_pool,
+            symbols: SymbolTable { functions, variables: Vec::new() },
+            encoding: OperandEncoding::Fixed
+        }
+    }
+}
@@ -18,13 +18,16 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{fs::read_to_string, io::{self, Write}, process::exit};
 
-use ccil::{Args, compiler::Compiler, constants::GPL_REPL_NOTICE, dprintln, parser::{Parser, token::Token}, vm::VirtualMachine};
+use ccil::{Args, Engine, compiler::Compiler, constants::GPL_REPL_NOTICE, dprintln, optimizer::fold::fold_constants, parser::{Parser, token::Token}, vm::{VirtualMachine, arithmetic::ArithmeticMode, chunk::{Chunk, OperandEncoding, resolve_timestamp}, register::RegisterMachine, section::Executable}};
 
-fn repl() -> ! {
+fn repl(engine: Engine, arithmetic: ArithmeticMode) -> ! {
     println!("{}", GPL_REPL_NOTICE);
 
     let compiler = Compiler::new();
     let mut vm = VirtualMachine::new(&compiler.string_pool);
+    let mut register_vm = RegisterMachine::new(&compiler.string_pool);
+    vm.set_arithmetic_mode(arithmetic);
+    register_vm.set_arithmetic_mode(arithmetic);
 
     loop {
         print!("ccil> ");
@@ -41,21 +44,37 @@ fn repl() -> ! {
             dprintln!("{:?}", expr);
         }
 
-        let compiled_chunk = compiler.compile(&parser.expressions);
-        vm.execute(compiled_chunk);
+        match engine {
+            Engine::Stack => vm.execute(compiler.compile(&parser.expressions)),
+            Engine::Register => register_vm.execute(&compiler.compile_registers(&parser.expressions))
+        }
     }
 }
 
 fn main() {
     let args = <Args as clap::Parser>::parse();
     if args.input_path.is_empty() {
-        repl();
+        repl(args.engine, args.arithmetic);
     }
 
-    let compiler = Compiler::new();
+    if args.engine == Engine::Register && (args.object || !args.output_path.is_empty()) {
+        eprintln!("The register engine only runs programs; compiled files hold stack bytecode");
+        exit(1);
+    }
+
+    if args.object && args.output_path.is_empty() {
+        eprintln!("Output file not specified, run --help for more info");
+        exit(1);
+    }
+
+    let timestamp = resolve_timestamp(if args.no_timestamp { Some(0) } else { args.timestamp });
+
+    let mut compiler = Compiler::new();
+    compiler.set_optimize(args.optimize);
     let mut vm = VirtualMachine::new(&compiler.string_pool);
+    vm.set_arithmetic_mode(args.arithmetic);
     
-    let source_file = match read_to_string(args.input_path) {
+    let source_file = match read_to_string(&args.input_path) {
         Ok(val) => val,
         Err(error) => {
             eprintln!("Failed to read input file: {}", error);
@@ -66,11 +85,49 @@ fn main() {
     let scan_result = Token::full_scan(&source_file);
     let mut parser = Parser::new(scan_result);
     parser.full_parse();
-    for expr in &parser.expressions {
+    let expressions = match args.optimize {
+        true => fold_constants(parser.expressions),
+        false => parser.expressions
+    };
+    for expr in &expressions {
         dprintln!("{:?}", expr);
     }
 
-    let compiled_chunk = compiler.compile(&parser.expressions);
+    if args.object {
+        // modules export their file stem, e.g. lib/util.ccil exports util
+        let module_name = args.input_path.rsplit('/').next().unwrap().split('.').next().unwrap();
+        let written = timestamp.and_then(|timestamp| compiler.compile_object(&expressions, module_name)
+            .and_then(|object| object.to_chunk(0, timestamp).to_file(&args.output_path)));
+        if let Err(error) = written {
+            eprintln!("{}", error);
+            exit(1);
+        }
+        exit(0);
+    }
+
+    if args.engine == Engine::Register {
+        let program = compiler.compile_registers(&expressions);
+        let mut register_vm = RegisterMachine::new(&compiler.string_pool);
+        register_vm.set_arithmetic_mode(args.arithmetic);
+        register_vm.execute(&program);
+        exit(0);
+    }
+
+    let compiled_chunk = compiler.compile(&expressions);
+
+    if !args.output_path.is_empty() {
+        let executable = Executable {
+            code: compiled_chunk,
+            string_pool: compiler.string_pool.borrow().clone(),
+            symbols: compiler.symbol_table(),
+            encoding: OperandEncoding::Fixed
+        };
+        if let Err(error) = timestamp.and_then(|timestamp| executable.to_chunk(0, timestamp).to_file(&args.output_path)) {
+            eprintln!("{}", error);
+            exit(1);
+        }
+        exit(0);
+    }
     
     vm.execute(compiled_chunk);
 }
@@ -0,0 +1,398 @@
+/*
+optimizer.rs: Peephole optimization of CCIL bytecode
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::constants::type_id_const;
+use crate::linker::{ObjectChunk, Relocation, RelocationKind};
+use crate::vm::chunk::{Chunk, ChunkError, ChunkOffset, OperandEncoding};
+use crate::vm::opcode::{Argument, OpCode, OpCodeLookup};
+use crate::vm::section::{Executable, SymbolTable};
+
+pub mod fold;
+
+// longest chain of jumps followed when threading, so that jump cycles terminate
+const MAX_THREAD_LENGTH: usize = 64;
+
+/// An instruction being rewritten.
+struct Line<'a> {
+    opcode: &'a OpCode<'a>,
+    /// Branch targets stay offsets into the original code until layout.
+    args: Vec<Argument>,
+    /// Offsets in the original code that now lead to this line.
+    origins: Vec<ChunkOffset>,
+    /// The original instruction whose operands this line still holds, if any.
+    source: Option<ChunkOffset>,
+    /// Lines holding relocated strings or symbols are left exactly as they are.
+    pinned: bool
+}
+
+/// Optimized code, and where everything in the original code ended up.
+pub struct Optimized {
+    pub code: Vec<u8>,
+    // original instruction offset -> new offset, for every instruction that still leads somewhere
+    offsets: FxHashMap<ChunkOffset, ChunkOffset>,
+    // original instruction offset -> new offset of the line that kept its operands
+    sources: FxHashMap<ChunkOffset, ChunkOffset>,
+    // every original instruction offset, in order
+    instruction_offsets: Vec<ChunkOffset>,
+    original_len: usize
+}
+
+fn is_branch(line: &Line) -> bool {
+    matches!(line.opcode.symbol, "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL")
+}
+
+impl Optimized {
+    /// Where an entry point or branch target of the original code now is.
+    /// Offsets past the end map to the new end, and offsets that were never
+    /// the start of an instruction are returned unchanged.
+    pub fn offset(&self, original: ChunkOffset) -> ChunkOffset {
+        if original >= self.original_len {
+            return self.code.len();
+        }
+        return self.offsets.get(&original).copied().unwrap_or(original);
+    }
+
+    /// Moves relocations to where their operands now are, dropping those whose
+    /// instructions were folded away.
+    pub fn relocate(&self, relocations: &[Relocation]) -> Vec<Relocation> {
+        let mut relocated = Vec::<Relocation>::new();
+        for relocation in relocations {
+            let index = self.instruction_offsets.partition_point(|offset| *offset <= relocation.offset) - 1;
+            let instruction = self.instruction_offsets[index];
+            if let Some(new_offset) = self.sources.get(&instruction) {
+                relocated.push(Relocation { offset: new_offset + relocation.offset - instruction, kind: relocation.kind.clone() });
+            }
+        }
+        return relocated;
+    }
+}
+
+/// Rewrites common instruction patterns in headerless code:
+/// - `CONST a, CONST b, op` and `CONST a, op` fold to one `CONST`, unless
+///   evaluating them would fail or overflow at runtime
+/// - `STORE v t, LOAD v` becomes `COPY 0, STORE v t` for numbers and strings
+/// - `CONST n, ADD` and `CONST n, SUB` become `ADDC`, and `LOAD v, ADDC n,
+///   STORE v number` becomes `INCVAR v n`
+/// - `COPY 0, IFZ` and `COPY 0, IFNZ` become `COPYIFZ` and `COPYIFNZ`
+/// - branches to a `JUMP` go straight to its target
+/// - `NOP`s are removed
+///
+/// Patterns never span a branch target, an entry point in `entries` or a
+/// return address. Instructions with string or symbol relocations, and
+/// constants with code relocations, are left alone, and branch targets are
+/// patched to the new layout. Without relocations nothing marks a constant
+/// as a code address, so such constants keep their old values.
+pub fn optimize(code: &Vec<u8>, encoding: OperandEncoding, entries: &[ChunkOffset], relocations: &[Relocation], lookup: &OpCodeLookup) -> Result<Optimized, ChunkError> {
+    let mut lines = Vec::<Line>::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = code.read_instruction(offset, encoding, lookup)?;
+        let mut line = Line { opcode: instruction.opcode, args: instruction.args, origins: vec![offset], source: Some(offset), pinned: false };
+        // code relocations are only followed through branch targets
+        line.pinned = relocations.iter()
+            .filter(|relocation| (offset..instruction.next_offset).contains(&relocation.offset))
+            .any(|relocation| match relocation.kind {
+                RelocationKind::String | RelocationKind::Symbol(_) => true,
+                RelocationKind::Code => !is_branch(&line),
+                RelocationKind::Variable => false
+            });
+        lines.push(line);
+        offset = instruction.next_offset;
+    }
+    let instruction_offsets: Vec<ChunkOffset> = lines.iter().map(|line| line.origins[0]).collect();
+    let index_of: FxHashMap<ChunkOffset, usize> = lines.iter().enumerate().map(|(i, line)| (line.origins[0], i)).collect();
+    for line in &lines {
+        let target = line.args.first().map(|target| *target as ChunkOffset);
+        if is_branch(line) && !line.pinned && let Some(target) = target
+            && target < code.len() && !index_of.contains_key(&target) {
+            return Err(ChunkError::MisalignedBranch { offset: line.origins[0], target });
+        }
+    }
+
+    thread_jumps(&mut lines, &index_of);
+
+    let mut labels: FxHashSet<ChunkOffset> = entries.iter().copied().collect();
+    labels.insert(0);
+    for (i, line) in lines.iter().enumerate() {
+        if is_branch(line) && !line.pinned {
+            labels.insert(line.args[0] as ChunkOffset);
+        }
+        if line.opcode.symbol == "CALL" && let Some(next) = lines.get(i + 1) {
+            labels.insert(next.origins[0]);
+        }
+    }
+
+    let mut out = Vec::<Line>::new();
+    let mut pending = Vec::<ChunkOffset>::new();
+    for mut line in lines {
+        line.origins.splice(0..0, pending.drain(..));
+        if line.opcode.symbol == "NOP" && !line.pinned {
+            pending.append(&mut line.origins);
+            continue;
+        }
+        out.push(line);
+        while fold_tail(&mut out, &labels, lookup) {}
+    }
+
+    return Ok(layout(out, pending, instruction_offsets, code.len(), encoding));
+}
+
+/// Points every branch that lands on an unconditional JUMP at that jump's target.
+fn thread_jumps(lines: &mut [Line], index_of: &FxHashMap<ChunkOffset, usize>) {
+    for i in 0..lines.len() {
+        if !is_branch(&lines[i]) || lines[i].pinned {
+            continue;
+        }
+        let mut target = lines[i].args[0];
+        for _ in 0..MAX_THREAD_LENGTH {
+            match index_of.get(&(target as ChunkOffset)).map(|index| &lines[*index]) {
+                Some(next) if next.opcode.symbol == "JUMP" && !next.pinned && next.args[0] != target => target = next.args[0],
+                _ => break
+            }
+        }
+        lines[i].args[0] = target;
+    }
+}
+
+/// Applies the first pattern that matches the end of `out`, and returns
+/// whether one did.
+fn fold_tail<'a>(out: &mut Vec<Line<'a>>, labels: &FxHashSet<ChunkOffset>, lookup: &OpCodeLookup<'a>) -> bool {
+    let labelled = |line: &Line| line.origins.iter().any(|origin| labels.contains(origin));
+    let n = out.len();
+
+    if n >= 3 && !out[n - 3].pinned && !out[n - 2].pinned
+        && out[n - 3].opcode.symbol == "CONST" && out[n - 2].opcode.symbol == "CONST"
+        && !labelled(&out[n - 2]) && !labelled(&out[n - 1])
+        && let Some(value) = fold_binary(out[n - 1].opcode.symbol, out[n - 3].args[0], out[n - 2].args[0]) {
+        out.truncate(n - 2);
+        out[n - 3].args[0] = value;
+        out[n - 3].source = None;
+        return true;
+    }
+
+    if n >= 2 && !out[n - 2].pinned && out[n - 2].opcode.symbol == "CONST" && !labelled(&out[n - 1])
+        && let Some(value) = fold_unary(out[n - 1].opcode.symbol, out[n - 2].args[0]) {
+        out.truncate(n - 1);
+        out[n - 2].args[0] = value;
+        out[n - 2].source = None;
+        return true;
+    }
+
+    if n >= 2 && !out[n - 2].pinned && out[n - 2].opcode.symbol == "CONST" && out[n - 1].opcode.symbol == "ADDC"
+        && !labelled(&out[n - 1]) && let Some(value) = out[n - 2].args[0].checked_add(out[n - 1].args[0]) {
+        out.truncate(n - 1);
+        out[n - 2].args[0] = value;
+        out[n - 2].source = None;
+        return true;
+    }
+
+    if n >= 2 && !out[n - 2].pinned && out[n - 2].opcode.symbol == "CONST" && !labelled(&out[n - 1]) {
+        let constant = out[n - 2].args[0];
+        let (amount, source) = match out[n - 1].opcode.symbol {
+            "ADD" => (Some(constant), out[n - 2].source),
+            "SUB" => (constant.checked_neg(), None),
+            _ => (None, None)
+        };
+        if let Some(amount) = amount {
+            let add = out.pop().unwrap();
+            let mut line = out.pop().unwrap();
+            line.origins.extend(add.origins);
+            out.push(Line { opcode: lookup.from_symbol("ADDC").unwrap(), args: vec![amount], origins: line.origins, source, pinned: false });
+            return true;
+        }
+    }
+
+    if n >= 3 && !out[n - 3].pinned && !out[n - 1].pinned
+        && out[n - 3].opcode.symbol == "LOAD" && out[n - 2].opcode.symbol == "ADDC" && out[n - 1].opcode.symbol == "STORE"
+        && out[n - 3].args[0] == out[n - 1].args[0] && out[n - 1].args[1] == type_id_const::NUMBER
+        && !labelled(&out[n - 2]) && !labelled(&out[n - 1]) {
+        let store = out.pop().unwrap();
+        let add = out.pop().unwrap();
+        let mut load = out.pop().unwrap();
+        load.origins.extend(add.origins);
+        load.origins.extend(store.origins);
+        out.push(Line { opcode: lookup.from_symbol("INCVAR").unwrap(), args: vec![load.args[0], add.args[0]], origins: load.origins, source: load.source, pinned: false });
+        return true;
+    }
+
+    if n >= 2 && out[n - 2].opcode.symbol == "COPY" && out[n - 2].args[0] == 0 && !out[n - 1].pinned
+        && matches!(out[n - 1].opcode.symbol, "IFZ" | "IFNZ") && !labelled(&out[n - 1]) {
+        let branch = out.pop().unwrap();
+        let mut copy = out.pop().unwrap();
+        let symbol = if branch.opcode.symbol == "IFZ" { "COPYIFZ" } else { "COPYIFNZ" };
+        copy.origins.extend(branch.origins);
+        out.push(Line { opcode: lookup.from_symbol(symbol).unwrap(), args: branch.args, origins: copy.origins, source: branch.source, pinned: false });
+        return true;
+    }
+
+    // loads give back exactly what was stored only for numbers and strings
+    if n >= 2 && out[n - 2].opcode.symbol == "STORE" && out[n - 1].opcode.symbol == "LOAD"
+        && out[n - 2].args[0] == out[n - 1].args[0] && !labelled(&out[n - 1])
+        && matches!(out[n - 2].args[1], type_id_const::NUMBER | type_id_const::STRING) {
+        let load = out.pop().unwrap();
+        let mut store = out.pop().unwrap();
+        let copy = Line { opcode: lookup.from_symbol("COPY").unwrap(), args: vec![0], origins: store.origins, source: None, pinned: false };
+        store.origins = load.origins;
+        out.push(copy);
+        out.push(store);
+        return true;
+    }
+    return false;
+}
+
+fn fold_binary(symbol: &str, a: Argument, b: Argument) -> Option<Argument> {
+    let shift = u32::try_from(b).ok().filter(|shift| *shift < Argument::BITS);
+    match symbol {
+        "ADD" => a.checked_add(b),
+        "SUB" => a.checked_sub(b),
+        "MUL" => a.checked_mul(b),
+        "DIV" => a.checked_div(b),
+        "MOD" => a.checked_rem(b),
+        "BAND" => Some(a & b),
+        "BOR" => Some(a | b),
+        "BXOR" => Some(a ^ b),
+        "AND" => Some(((a != 0) && (b != 0)) as Argument),
+        "OR" => Some(((a != 0) || (b != 0)) as Argument),
+        "XOR" => Some(((a != 0) != (b != 0)) as Argument),
+        "SHL" => shift.map(|shift| a << shift),
+        "SHRL" => shift.map(|shift| ((a as u32) >> shift) as Argument),
+        "SHRA" => shift.map(|shift| a >> shift),
+        _ => None
+    }
+}
+
+fn fold_unary(symbol: &str, a: Argument) -> Option<Argument> {
+    match symbol {
+        "NEG" => a.checked_neg(),
+        "BNOT" => Some(!a),
+        "NOT" => Some((a == 0) as Argument),
+        _ => None
+    }
+}
+
+/// Encodes the rewritten lines, patching branch targets. Compact operands are
+/// sized by their targets and targets by the operand sizes, so offsets are
+/// recomputed until they settle; sizes only grow, so this terminates.
+fn layout(lines: Vec<Line>, end_origins: Vec<ChunkOffset>, instruction_offsets: Vec<ChunkOffset>, original_len: usize, encoding: OperandEncoding) -> Optimized {
+    let mut index_of = FxHashMap::<ChunkOffset, usize>::default();
+    for (i, line) in lines.iter().enumerate() {
+        for origin in &line.origins {
+            index_of.insert(*origin, i);
+        }
+    }
+    for origin in &end_origins {
+        index_of.insert(*origin, lines.len());
+    }
+    let target_index = |line: &Line| -> usize {
+        let target = line.args[0] as ChunkOffset;
+        if target >= original_len { lines.len() } else { index_of[&target] }
+    };
+
+    let mut offsets = vec![0; lines.len() + 1];
+    let mut new_code = Vec::<u8>::new();
+    loop {
+        new_code.clear();
+        let mut next_offsets = Vec::<ChunkOffset>::with_capacity(offsets.len());
+        for line in &lines {
+            next_offsets.push(new_code.len());
+            new_code.write_op(line.opcode);
+            for (i, arg) in line.args.iter().enumerate() {
+                let arg = if i == 0 && is_branch(line) && !line.pinned { offsets[target_index(line)] as Argument } else { *arg };
+                new_code.write_operand(arg, encoding);
+            }
+        }
+        next_offsets.push(new_code.len());
+        if next_offsets == offsets {
+            break;
+        }
+        offsets = next_offsets;
+    }
+
+    let mut moved = FxHashMap::<ChunkOffset, ChunkOffset>::default();
+    let mut sources = FxHashMap::<ChunkOffset, ChunkOffset>::default();
+    for (i, line) in lines.iter().enumerate() {
+        for origin in &line.origins {
+            moved.insert(*origin, offsets[i]);
+        }
+        if let Some(source) = line.source {
+            sources.insert(source, offsets[i]);
+        }
+    }
+    for origin in &end_origins {
+        moved.insert(*origin, new_code.len());
+    }
+    return Optimized { code: new_code, offsets: moved, sources, instruction_offsets, original_len };
+}
+
+/// Optimizes the code of an executable, moving its function symbols along.
+/// Executables have no relocations, so a constant that could be a code
+/// address cannot be told apart from a number; if the new layout would move
+/// an instruction that any constant points at, the code is left unchanged.
+pub fn optimize_executable(executable: &Executable, lookup: &OpCodeLookup) -> Result<Executable, ChunkError> {
+    let entries: Vec<ChunkOffset> = executable.symbols.functions.iter().map(|(_, offset)| *offset).collect();
+    let optimized = optimize(&executable.code, executable.encoding, &entries, &[], lookup)?;
+    if moves_constant_targets(&executable.code, executable.encoding, &optimized, lookup)? {
+        return Ok(Executable {
+            code: executable.code.clone(),
+            string_pool: executable.string_pool.clone(),
+            symbols: executable.symbols.clone(),
+            encoding: executable.encoding
+        });
+    }
+    let functions = executable.symbols.functions.iter()
+        .map(|(name, offset)| (name.clone(), optimized.offset(*offset)))
+        .collect();
+    return Ok(Executable {
+        code: optimized.code,
+        string_pool: executable.string_pool.clone(),
+        symbols: SymbolTable { functions, variables: executable.symbols.variables.clone() },
+        encoding: executable.encoding
+    });
+}
+
+/// Whether any `CONST` in the original code holds the offset of an
+/// instruction, or of the end, that the optimized layout moves.
+fn moves_constant_targets(code: &Vec<u8>, encoding: OperandEncoding, optimized: &Optimized, lookup: &OpCodeLookup) -> Result<bool, ChunkError> {
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = code.read_instruction(offset, encoding, lookup)?;
+        if instruction.opcode.symbol == "CONST" && let Ok(target) = ChunkOffset::try_from(instruction.args[0])
+            && target <= code.len() && optimized.offset(target) != target {
+            return Ok(true);
+        }
+        offset = instruction.next_offset;
+    }
+    return Ok(false);
+}
+
+/// Optimizes the code of an object, moving its exports and relocations along.
+pub fn optimize_object(object: &ObjectChunk, lookup: &OpCodeLookup) -> Result<ObjectChunk, ChunkError> {
+    let entries: Vec<ChunkOffset> = object.exports.iter().map(|(_, offset)| *offset).collect();
+    let optimized = optimize(&object.code, OperandEncoding::Fixed, &entries, &object.relocations, lookup)?;
+    let exports = object.exports.iter()
+        .map(|(name, offset)| (name.clone(), optimized.offset(*offset)))
+        .collect();
+    return Ok(ObjectChunk {
+        relocations: optimized.relocate(&object.relocations),
+        code: optimized.code,
+        string_pool: object.string_pool.clone(),
+        exports
+    });
+}
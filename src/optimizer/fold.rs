@@ -0,0 +1,160 @@
+/*
+optimizer/fold.rs: Constant folding and simplification of parsed expressions
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::parser::{expr::Expr, token::Token};
+
+/// Simplifies every statement of a program, dropping `if` and `while`
+/// statements whose condition is always false.
+pub fn fold_constants(expressions: Vec<Expr>) -> Vec<Expr> {
+    return expressions.into_iter().map(fold).filter(|expr| !is_dead(expr)).collect();
+}
+
+/// Simplifies an expression bottom-up:
+/// - operators on literals are evaluated, unless that would fail or overflow
+///   at runtime, so e.g. `1/0` is left for the VM to report
+/// - `x+0`, `0+x`, `x-0`, `x*1`, `1*x` and `x/1` become `x`; like the compiler,
+///   these assume arithmetic is only done on numbers
+/// - parentheses are dropped, as the tree already records precedence
+/// - `if` and `while` statements with a false, zero or null condition are
+///   removed from bodies
+pub fn fold(expression: Expr) -> Expr {
+    use Expr::*;
+    match expression {
+        Unary(token, operand) => {
+            let operand = fold(*operand);
+            match fold_unary(&token, &operand) {
+                Some(value) => Literal(value),
+                None => Unary(token, Box::new(operand))
+            }
+        },
+        Binary(Token::Equals, target, value) => Binary(Token::Equals, target, Box::new(fold(*value))),
+        Binary(token, left, right) => {
+            let (left, right) = (fold(*left), fold(*right));
+            if let Some(value) = fold_binary(&token, &left, &right) {
+                return Literal(value);
+            }
+            match identity(&token, &left, &right) {
+                Some(Side::Left) => left,
+                Some(Side::Right) => right,
+                None => Binary(token, Box::new(left), Box::new(right))
+            }
+        },
+        Grouping(inner) => fold(*inner),
+        CurlyGrouping(inner) => CurlyGrouping(Box::new(fold(*inner))),
+        SquareGrouping(inner) => SquareGrouping(Box::new(fold(*inner))),
+        CommaSeparatedList(items) => CommaSeparatedList(items.into_iter().map(|expr| Box::new(fold(*expr))).collect()),
+        Subexprs(statements) => Subexprs(statements.into_iter().map(|expr| Box::new(fold(*expr))).filter(|statement| !is_dead(statement)).collect()),
+        FunctionDeclaration(name, args, body) => FunctionDeclaration(name, args, Box::new(fold(*body))),
+        FunctionCall(token, args) => FunctionCall(token, Box::new(fold(*args))),
+        ForLoop(args, body) => ForLoop(Box::new(fold(*args)), Box::new(fold(*body))),
+        WhileLoop(condition, body) => WhileLoop(Box::new(fold(*condition)), Box::new(fold(*body))),
+        PrintStatement(value) => PrintStatement(Box::new(fold(*value))),
+        ReturnStatement(value) => ReturnStatement(Box::new(fold(*value))),
+        IfStatement(condition, body) => IfStatement(Box::new(fold(*condition)), Box::new(fold(*body))),
+        other => other
+    }
+}
+
+/// Whether a statement never runs its body: an `if` or `while` whose
+/// (already folded) condition is a false literal.
+fn is_dead(statement: &Expr) -> bool {
+    match statement {
+        Expr::IfStatement(condition, _) | Expr::WhileLoop(condition, _) => matches!(
+            condition.as_ref(),
+            Expr::Literal(Token::Boolean(false) | Token::Number(0) | Token::Null)
+        ),
+        _ => false
+    }
+}
+
+fn fold_unary(token: &Token, operand: &Expr) -> Option<Token> {
+    let Expr::Literal(value) = operand else {
+        return None;
+    };
+    match (token, value) {
+        (Token::Minus, Token::Number(n)) => n.checked_neg().map(Token::Number),
+        (Token::Tilde, Token::Number(n)) => Some(Token::Number(!n)),
+        (Token::Bang, Token::Boolean(b)) => Some(Token::Boolean(!b)),
+        _ => None
+    }
+}
+
+fn fold_binary(token: &Token, left: &Expr, right: &Expr) -> Option<Token> {
+    use Token::*;
+    let (Expr::Literal(a), Expr::Literal(b)) = (left, right) else {
+        return None;
+    };
+    if let (Number(a), Number(b)) = (a, b) {
+        let (a, b) = (*a, *b);
+        let shift = u32::try_from(b).ok().filter(|shift| *shift < i32::BITS);
+        return match token {
+            Plus => a.checked_add(b).map(Number),
+            Minus => a.checked_sub(b).map(Number),
+            Star => a.checked_mul(b).map(Number),
+            Slash => a.checked_div(b).map(Number),
+            Percent => a.checked_rem(b).map(Number),
+            SingleAnd => Some(Number(a & b)),
+            SingleOr => Some(Number(a | b)),
+            Carat => Some(Number(a ^ b)),
+            DoubleLessThan => shift.map(|shift| Number(a << shift)),
+            DoubleGreaterThan => shift.map(|shift| Number(a >> shift)),
+            DoubleEqual => Some(Boolean(a == b)),
+            BangEqual => Some(Boolean(a != b)),
+            LessThan => Some(Boolean(a < b)),
+            LessThanEqual => Some(Boolean(a <= b)),
+            GreaterThan => Some(Boolean(a > b)),
+            GreaterThanEqual => Some(Boolean(a >= b)),
+            _ => None
+        };
+    }
+    if let (Boolean(a), Boolean(b)) = (a, b) {
+        return match token {
+            And => Some(Boolean(*a && *b)),
+            Or => Some(Boolean(*a || *b)),
+            DoubleEqual => Some(Boolean(a == b)),
+            BangEqual => Some(Boolean(a != b)),
+            _ => None
+        };
+    }
+    return None;
+}
+
+enum Side {
+    Left,
+    Right
+}
+
+/// Which operand a binary expression reduces to, if the other is an identity
+/// element of the operator.
+fn identity(token: &Token, left: &Expr, right: &Expr) -> Option<Side> {
+    // adding a number to a string literal is not an identity
+    if [left, right].iter().any(|expr| matches!(expr, Expr::Literal(Token::String(_)))) {
+        return None;
+    }
+    let number = |expr: &Expr| match expr {
+        Expr::Literal(Token::Number(n)) => Some(*n),
+        _ => None
+    };
+    match (token, number(left), number(right)) {
+        (Token::Plus | Token::Minus, _, Some(0)) => Some(Side::Left),
+        (Token::Plus, Some(0), _) => Some(Side::Right),
+        (Token::Star | Token::Slash, _, Some(1)) => Some(Side::Left),
+        (Token::Star, Some(1), _) => Some(Side::Right),
+        _ => None
+    }
+}
@@ -160,7 +160,7 @@ impl Parser {
         };
 
         // TODO: cleanup to use generate_until_token
-        while &self.peek() != &opposite {
+        while self.peek() != opposite {
             let expr = self.generate_expression();
             self.floating_expressions.push(expr);
         }
@@ -238,7 +238,7 @@ impl Parser {
         match binary {
             Expr::Binary(_, ref lhs, _) => {
                 // if left hand side is not a variable expression
-                if !(&**lhs).is_type(&ExprType::Variable) {
+                if !lhs.is_type(&ExprType::Variable) {
                     self.raise_parsing_error("Illegal assignemt".to_string());
                 }
             }
@@ -269,7 +269,7 @@ impl Parser {
         match &args {
             Expr::CommaSeparatedList(arg_list) => {
                 for arg in arg_list {
-                    if !(&**arg).is_type(&ExprType::Variable) {
+                    if !arg.is_type(&ExprType::Variable) {
                         self.raise_parsing_error(
                             "Non-variable found in function declaration args".to_owned()
                         )
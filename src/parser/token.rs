@@ -140,7 +140,7 @@ impl Token {
 
     pub fn get_float(&self) -> Option<&f64> {
         match self {
-            Token::Float(f) => Some(&*f),
+            Token::Float(f) => Some(f),
             _ => None
         }
     }
@@ -207,20 +207,20 @@ impl Token {
             }
             '<' => {
                 if slice_to_end.len() > 1 && &slice_to_end[1..2] == "=" {
-                    (GreaterThanEqual, 2)
+                    (LessThanEqual, 2)
                 } else if slice_to_end.len() > 1 && &slice_to_end[1..2] == "<" {
-                    (DoubleGreaterThan, 2)
+                    (DoubleLessThan, 2)
                 } else {
-                    (GreaterThan, 1)
+                    (LessThan, 1)
                 }
             }
             '>' => {
                 if slice_to_end.len() > 1 && &slice_to_end[1..2] == "=" {
-                    (LessThanEqual, 2)
+                    (GreaterThanEqual, 2)
                 } else if slice_to_end.len() > 1 && &slice_to_end[1..2] == ">" {
-                    (DoubleLessThan, 2)
+                    (DoubleGreaterThan, 2)
                 } else {
-                    (LessThan, 1)
+                    (GreaterThan, 1)
                 }
             }
             '&' => {
@@ -21,22 +21,51 @@ use std::{cell::RefCell, fs::File};
 use rustc_hash::FxHashMap;
 
 use crate::compiler::VariableId;
-use crate::{dprint, dprintln};
-use crate::vm::{chunk::Chunk, opcode::OpCodeLookup, stack::{Stack, StackPointer, VecStack}, variable_value::VariableValue};
+use crate::vm::{arithmetic::ArithmeticMode, chunk::{ChunkOffset, OperandEncoding}, decoded::DecodedChunk, memory::MemoryAccount, opcode::OpCodeLookup, section::Executable, stack::{Stack, StackItem, VecStack}, variable_value::VariableValue};
 
+pub mod arithmetic;
+pub mod cfg;
 pub mod chunk;
+pub mod decoded;
 pub mod handle_op;
+pub mod hexdump;
+pub mod memory;
 pub mod opcode;
+pub mod register;
+pub mod section;
 pub mod stack;
 pub mod variable_value;
 
 
+/// How a run of the VM ended, when it did not fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The chunk ran to completion.
+    Finished,
+    /// The fuel ran out before the next instruction, which `resume` continues from.
+    OutOfFuel
+}
+
+/// A run that ran out of fuel, kept so that it can be resumed.
+struct Suspended {
+    program: DecodedChunk,
+    index: usize
+}
+
 pub struct VirtualMachine<'a, 'b> {
     lookup: OpCodeLookup<'a>,
     stack: VecStack,
     variables: FxHashMap<VariableId, VariableValue>,
     string_pool: &'b RefCell<Vec<u8>>,
-    opened_files: Vec<File>
+    opened_files: Vec<File>,
+    operand_encoding: OperandEncoding,
+    arithmetic: ArithmeticMode,
+    // instructions left to execute, or None for no limit
+    fuel: Option<u64>,
+    suspended: Option<Suspended>,
+    memory: MemoryAccount,
+    // how much of the string pool has been charged to `memory`
+    string_pool_charged: usize
 }
 
 impl<'b> VirtualMachine<'_, 'b> {
@@ -46,44 +75,217 @@ impl<'b> VirtualMachine<'_, 'b> {
             stack: VecStack::new(),
             variables: FxHashMap::default(),
             string_pool,
-            opened_files: Vec::new()
+            opened_files: Vec::new(),
+            operand_encoding: OperandEncoding::Fixed,
+            arithmetic: ArithmeticMode::default(),
+            fuel: None,
+            suspended: None,
+            memory: MemoryAccount::new(),
+            string_pool_charged: 0
         }
     }
 
+    /// Sets the operand encoding of headerless chunks passed to execute.
+    pub fn set_operand_encoding(&mut self, encoding: OperandEncoding) {
+        self.operand_encoding = encoding;
+    }
+
+    /// Sets what integer arithmetic does on overflow; checked by default.
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) {
+        self.arithmetic = mode;
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        return self.arithmetic;
+    }
+
+    /// Limits how many more instructions the VM executes, or removes the limit.
+    /// Running out stops execution with `RunStatus::OutOfFuel`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Instructions left before the VM runs out of fuel, if limited.
+    pub fn fuel(&self) -> Option<u64> {
+        return self.fuel;
+    }
+
+    /// Adds fuel and continues the run that last ran out of it, where it stopped.
+    pub fn resume(&mut self, fuel: u64) -> Result<RunStatus, String> {
+        let Some(suspended) = self.suspended.take() else {
+            return Err("No run is waiting for fuel".to_string());
+        };
+        self.fuel = Some(self.fuel.unwrap_or(0) + fuel);
+        return self.run_decoded(suspended.program, suspended.index);
+    }
+
+    /// Executes a chunk, panicking on runtime errors. Running out of fuel
+    /// returns early, and the run can be resumed.
     pub fn execute(&mut self, chunk_to_execute: Vec<u8>) {
-        let mut offset = 0;
-
-        while offset < chunk_to_execute.len() {
-            // Get opcode at current pos (guaranteed to be opcode by invariant)
-            dprint!("{} ", offset);
-            let chunk_code = match self.lookup.from_byte(chunk_to_execute[offset]) {
-                Some(opcode) => opcode,
-                None => panic!("Unknown opcode with value 0x{:02x}", chunk_to_execute[offset])
-            };
-            
-            let mut args = Vec::<StackPointer>::new();
-            for i in 0..chunk_code.num_params {
-                args.push(chunk_to_execute.read_arg(offset + 1 + 4*i) as StackPointer);
+        self.run(&chunk_to_execute, 0);
+    }
+
+    /// Calls the named function of a loaded executable, whose string pool the
+    /// VM must have been created with. Arguments are pushed in reverse order,
+    /// followed by the return address, as a CALL would. Returns whatever the
+    /// function leaves on the stack, bottom to top, including any arguments it
+    /// did not discard. Running out of fuel is an error, though the call can
+    /// still be resumed.
+    pub fn invoke_function(&mut self, executable: &Executable, name: &str, args: &[StackItem]) -> Result<Vec<StackItem>, String> {
+        let function_offset = match executable.symbols.function(name) {
+            Some(val) => val,
+            None => return Err(format!("Unknown function {}", name))
+        };
+        if function_offset >= executable.code.len() {
+            return Err(format!("Function {} at offset {} is past the end of the code", name, function_offset));
+        }
+
+        let depth = self.stack.len();
+        match self.call_function(executable, function_offset, args) {
+            Ok(RunStatus::Finished) => {},
+            Ok(RunStatus::OutOfFuel) => return Err(format!("Function {} ran out of fuel", name)),
+            Err(err) => {
+                // leave the stack as it was before the call
+                self.stack.truncate(depth);
+                return Err(err);
             }
+        }
 
-            // Run handler for op, we get next offset
-            match (chunk_code.handler)(self, &args, offset) {
-                Ok(Some(new_offset)) => { offset = new_offset; },
-                Ok(None) => { break; }, // program exited
-                Err(err) => { panic!("Error at chunk offset {}: {}", offset, err); }
+        let mut results = Vec::<StackItem>::new();
+        while self.stack.len() > depth {
+            results.push(self.stack.pop().unwrap());
+        }
+        results.reverse();
+        Ok(results)
+    }
+
+    /// Pushes the arguments and return address for `invoke_function` and runs
+    /// the function.
+    fn call_function(&mut self, executable: &Executable, function_offset: ChunkOffset, args: &[StackItem]) -> Result<RunStatus, String> {
+        for arg in args.iter().rev() {
+            self.stack.push(*arg)?;
+        }
+        // returning to the end of the chunk stops execution
+        self.stack.push(executable.code.len() as StackItem)?;
+
+        self.operand_encoding = executable.encoding;
+        return self.try_run(&executable.code, function_offset);
+    }
+
+    /// Executes a chunk like `execute`, but returns the first runtime error
+    /// instead of panicking. The stack and variables keep whatever state the
+    /// chunk left them in, up to the failing instruction.
+    pub fn try_execute(&mut self, chunk_to_execute: &Vec<u8>) -> Result<RunStatus, String> {
+        return self.try_run(chunk_to_execute, 0);
+    }
+
+    /// The operand stack, bottom first.
+    pub fn stack(&self) -> &VecStack {
+        return &self.stack;
+    }
+
+    /// Sets how many items the stack may hold before pushing fails with a
+    /// stack overflow (`stack::DEFAULT_MAX_DEPTH` unless set).
+    pub fn set_max_stack_depth(&mut self, max_depth: usize) {
+        self.stack.set_max_depth(max_depth);
+    }
+
+    /// How many items are on the stack.
+    pub fn stack_depth(&self) -> usize {
+        return self.stack.len();
+    }
+
+    /// The most items the stack has held at once.
+    pub fn peak_stack_depth(&self) -> usize {
+        return self.stack.peak_depth();
+    }
+
+    /// Limits the bytes held for the script's variables and string pool, or
+    /// removes the limit. Going over it is a runtime error.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    /// Bytes held for the script, counted against the memory limit.
+    pub fn memory_used(&self) -> usize {
+        return self.memory.used();
+    }
+
+    /// Every global variable that has been stored to.
+    pub fn variables(&self) -> &FxHashMap<VariableId, VariableValue> {
+        return &self.variables;
+    }
+
+    fn run(&mut self, chunk_to_execute: &Vec<u8>, start: ChunkOffset) -> RunStatus {
+        return match self.try_run(chunk_to_execute, start) {
+            Ok(status) => status,
+            Err(err) => panic!("{}", err)
+        };
+    }
+
+    fn try_run(&mut self, chunk_to_execute: &Vec<u8>, start: ChunkOffset) -> Result<RunStatus, String> {
+        let program = match DecodedChunk::decode(chunk_to_execute, self.operand_encoding, &self.lookup) {
+            Ok(program) => program,
+            Err(err) => return Err(format!("Failed to decode chunk: {}", err))
+        };
+        let Some(index) = program.index_of(start) else {
+            return Err(format!("Cannot start at chunk offset {}, which is not the start of an instruction", start));
+        };
+        // a new run abandons any run waiting for fuel
+        self.suspended = None;
+
+        // the string pool only grows, as strings are compiled or assembled
+        let string_pool_len = self.string_pool.borrow().len();
+        if string_pool_len > self.string_pool_charged {
+            if let Err(err) = self.memory.charge(string_pool_len - self.string_pool_charged) {
+                return Err(format!("Failed to load string pool: {}", err));
             }
-            dprintln!("\t{:?}", self.stack);
+            self.string_pool_charged = string_pool_len;
         }
+        return self.run_decoded(program, index);
     }
 
-    pub fn get_string(&self, start_index: usize) -> String {
-        let borrowed_string_pool = self.string_pool.borrow();
-        let mut string_bytes = Vec::<u8>::new();
-        let mut i = start_index;
-        while borrowed_string_pool[i] != 0 {
-            string_bytes.push(borrowed_string_pool[i]);
-            i += 1
+    fn run_decoded(&mut self, program: DecodedChunk, mut index: usize) -> Result<RunStatus, String> {
+        let debug = crate::debug_enabled();
+
+        while index < program.ops.len() {
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    self.suspended = Some(Suspended { program, index });
+                    return Ok(RunStatus::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+
+            if debug {
+                print!("{} {:?}", program.offset_of(index), program.ops[index]);
+            }
+
+            // Execute op, we get the index of the next one
+            match self.step(&program, index) {
+                Ok(Some(next_index)) => { index = next_index; },
+                Ok(None) => { break; }, // program exited
+                Err(err) => { return Err(format!("Error at chunk offset {}: {}", program.offset_of(index), err)); }
+            }
+            if debug {
+                println!("\t{:?}", self.stack);
+            }
         }
-        String::from_utf8(string_bytes).unwrap()
+        Ok(RunStatus::Finished)
     }
+
+    pub fn get_string(&self, start_index: usize) -> Result<String, String> {
+        return read_string(&self.string_pool.borrow(), start_index);
+    }
+}
+
+/// Reads the null-terminated string starting at an offset into a string pool.
+pub fn read_string(string_pool: &[u8], start_index: usize) -> Result<String, String> {
+    let Some(bytes) = string_pool.get(start_index..) else {
+        return Err(format!("String index {} is outside the string pool", start_index));
+    };
+    let Some(len) = bytes.iter().position(|byte| *byte == 0) else {
+        return Err(format!("String at index {} is not null-terminated", start_index));
+    };
+    return Ok(String::from_utf8_lossy(&bytes[..len]).into_owned());
 }
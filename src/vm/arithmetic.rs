@@ -0,0 +1,141 @@
+/*
+vm/arithmetic.rs: Integer arithmetic with defined overflow behaviour
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+
+use crate::vm::stack::StackItem;
+
+/// What integer arithmetic does when a result does not fit in 32 bits.
+/// Dividing by zero is an error either way.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    /// Overflow, and shifts by less than 0 or more than 31 bits, are runtime errors
+    #[default]
+    Checked,
+    /// Results wrap around in two's complement, and shift amounts are taken modulo 32
+    Wrapping
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    Overflow,
+    DivisionByZero,
+    InvalidShift(StackItem)
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "integer overflow"),
+            ArithmeticError::DivisionByZero => write!(f, "division by zero"),
+            ArithmeticError::InvalidShift(amount) => write!(f, "invalid shift amount {}", amount)
+        }
+    }
+}
+
+impl From<ArithmeticError> for String {
+    fn from(error: ArithmeticError) -> Self {
+        return error.to_string();
+    }
+}
+
+impl ArithmeticMode {
+    #[inline]
+    pub fn add(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return match self {
+            ArithmeticMode::Checked => a.checked_add(b).ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_add(b))
+        };
+    }
+
+    #[inline]
+    pub fn sub(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return match self {
+            ArithmeticMode::Checked => a.checked_sub(b).ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_sub(b))
+        };
+    }
+
+    #[inline]
+    pub fn mul(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return match self {
+            ArithmeticMode::Checked => a.checked_mul(b).ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_mul(b))
+        };
+    }
+
+    /// Quotient rounded towards zero; `MIN / -1` is the only overflow.
+    #[inline]
+    pub fn div(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        if b == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        return match self {
+            ArithmeticMode::Checked => a.checked_div(b).ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_div(b))
+        };
+    }
+
+    /// Remainder with the sign of `a`; `MIN % -1` overflows when checked and
+    /// is 0 when wrapping.
+    #[inline]
+    pub fn rem(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        if b == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        return match self {
+            ArithmeticMode::Checked => a.checked_rem(b).ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_rem(b))
+        };
+    }
+
+    #[inline]
+    pub fn neg(self, a: StackItem) -> Result<StackItem, ArithmeticError> {
+        return match self {
+            ArithmeticMode::Checked => a.checked_neg().ok_or(ArithmeticError::Overflow),
+            ArithmeticMode::Wrapping => Ok(a.wrapping_neg())
+        };
+    }
+
+    /// Shifts left; bits shifted out are lost in either mode.
+    #[inline]
+    pub fn shl(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return Ok(a.wrapping_shl(self.shift_amount(b)?));
+    }
+
+    /// Shifts right, filling the most significant bits with zeros.
+    #[inline]
+    pub fn shrl(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return Ok((a as u32).wrapping_shr(self.shift_amount(b)?) as StackItem);
+    }
+
+    /// Shifts right, copying the most significant bit rightwards.
+    #[inline]
+    pub fn shra(self, a: StackItem, b: StackItem) -> Result<StackItem, ArithmeticError> {
+        return Ok(a.wrapping_shr(self.shift_amount(b)?));
+    }
+
+    // wrapping shifts take the amount modulo 32 themselves
+    #[inline]
+    fn shift_amount(self, b: StackItem) -> Result<u32, ArithmeticError> {
+        return match self {
+            ArithmeticMode::Checked if !(0..StackItem::BITS as StackItem).contains(&b) => Err(ArithmeticError::InvalidShift(b)),
+            _ => Ok(b as u32)
+        };
+    }
+}
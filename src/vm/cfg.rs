@@ -0,0 +1,149 @@
+/*
+vm/cfg.rs: Control-flow graphs of CCIL bytecode
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{collections::BTreeSet, fmt};
+
+use petgraph::dot::Dot;
+use petgraph::graph::{DiGraph, NodeIndex};
+use rustc_hash::FxHashMap;
+
+use crate::vm::chunk::{Chunk, ChunkError, ChunkOffset, Instruction, OperandEncoding};
+use crate::vm::opcode::OpCodeLookup;
+
+/// A run of instructions that is only entered at its first instruction and
+/// only left after its last.
+pub struct BasicBlock<'a> {
+    pub start: ChunkOffset,
+    pub instructions: Vec<Instruction<'a>>
+}
+
+/// How control passes from one block to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// The branch of a JUMP, IFZ or IFNZ.
+    Taken,
+    /// The next block in the chunk, including where a CALL returns to.
+    Fallthrough,
+    /// The function entered by a CALL.
+    Call
+}
+
+pub type ControlFlowGraph<'a> = DiGraph<BasicBlock<'a>, EdgeKind>;
+
+impl fmt::Display for BasicBlock<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.start)?;
+        for instruction in &self.instructions {
+            write!(f, "{}", instruction.opcode.symbol)?;
+            for arg in &instruction.args {
+                write!(f, " {}", arg)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeKind::Taken => write!(f, "taken"),
+            EdgeKind::Fallthrough => write!(f, "fallthrough"),
+            EdgeKind::Call => write!(f, "call")
+        }
+    }
+}
+
+/// The target operand of a branch, or None for any other instruction.
+fn branch_target(instruction: &Instruction) -> Option<ChunkOffset> {
+    match instruction.opcode.symbol {
+        "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL" if instruction.args[0] >= 0 => Some(instruction.args[0] as ChunkOffset),
+        _ => None
+    }
+}
+
+/// Decodes a headerless chunk and builds its control-flow graph.
+pub fn from_chunk<'a>(chunk: &Vec<u8>, encoding: OperandEncoding, lookup: &'a OpCodeLookup<'a>) -> Result<ControlFlowGraph<'a>, ChunkError> {
+    let mut instructions = Vec::<Instruction>::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let instruction = chunk.read_instruction(offset, encoding, lookup)?;
+        offset = instruction.next_offset;
+        instructions.push(instruction);
+    }
+    return Ok(from_instructions(instructions));
+}
+
+/// Splits decoded instructions into basic blocks, at branch targets and after
+/// every branch and RETURN, and links the blocks together.
+/// Branches to the end of the chunk or into the middle of an instruction get
+/// no edge.
+pub fn from_instructions(instructions: Vec<Instruction<'_>>) -> ControlFlowGraph<'_> {
+    let mut leaders = BTreeSet::<ChunkOffset>::new();
+    if let Some(first) = instructions.first() {
+        leaders.insert(first.offset);
+    }
+    for instruction in &instructions {
+        if let Some(target) = branch_target(instruction) {
+            leaders.insert(target);
+        }
+        if matches!(instruction.opcode.symbol, "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" | "CALL" | "RETURN") {
+            leaders.insert(instruction.next_offset);
+        }
+    }
+
+    let mut graph = ControlFlowGraph::new();
+    let mut blocks = FxHashMap::<ChunkOffset, NodeIndex>::default();
+    for instruction in instructions {
+        if leaders.contains(&instruction.offset) || graph.node_count() == 0 {
+            blocks.insert(instruction.offset, graph.add_node(BasicBlock { start: instruction.offset, instructions: Vec::new() }));
+        }
+        let last = NodeIndex::new(graph.node_count() - 1);
+        graph[last].instructions.push(instruction);
+    }
+
+    for index in graph.node_indices() {
+        let last = graph[index].instructions.last().unwrap();
+        let target = branch_target(last).and_then(|target| blocks.get(&target).copied());
+        let next = blocks.get(&last.next_offset).copied();
+        let mut edges = Vec::<(NodeIndex, EdgeKind)>::new();
+        match last.opcode.symbol {
+            "JUMP" | "IFZ" | "IFNZ" | "COPYIFZ" | "COPYIFNZ" => edges.extend(target.map(|target| (target, EdgeKind::Taken))),
+            "CALL" => edges.extend(target.map(|target| (target, EdgeKind::Call))),
+            _ => {}
+        }
+        if !matches!(last.opcode.symbol, "JUMP" | "RETURN") {
+            edges.extend(next.map(|next| (next, EdgeKind::Fallthrough)));
+        }
+        for (to, kind) in edges {
+            graph.add_edge(index, to, kind);
+        }
+    }
+    return graph;
+}
+
+/// Renders a control-flow graph as Graphviz DOT, one box per block.
+pub fn to_dot(graph: &ControlFlowGraph) -> String {
+    let dot = Dot::with_attr_getters(
+        graph,
+        &[],
+        &|_, _| String::new(),
+        &|_, _| "shape = box fontname = monospace".to_string()
+    );
+    return format!("{}", dot);
+}
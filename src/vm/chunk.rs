@@ -16,42 +16,157 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::{error::Error, fmt, io};
+
 use chrono::Utc;
 
-use crate::constants::{BYTECODE_HEADER_SIZE, CCIL_MAGIC_BYTE_0, CCIL_MAGIC_BYTE_1};
-use crate::vm::opcode::{Argument, OpCode};
+use crate::constants::{BYTECODE_HEADER_FLAGS_OFFSET, BYTECODE_HEADER_SIZE, CCIL_MAGIC_BYTE_0, CCIL_MAGIC_BYTE_1, header_flag_const};
+use crate::vm::opcode::{Argument, MAX_OPERANDS, Op, OpCode, OpCodeLookup};
 use crate::vm::stack::StackPointer;
 
 pub type ChunkOffset = usize;
 
+/// Picks the header timestamp in UTC Unix seconds: an explicit value wins, then
+/// `SOURCE_DATE_EPOCH` (for reproducible builds), then the current time. The
+/// header only holds an unsigned 32-bit timestamp, so anything else is an error.
+pub fn resolve_timestamp(explicit: Option<i64>) -> Result<i64, ChunkError> {
+    let timestamp = match explicit {
+        Some(timestamp) => timestamp,
+        None => match std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.trim().parse::<i64>().ok()) {
+            Some(timestamp) => timestamp,
+            None => Utc::now().timestamp()
+        }
+    };
+    if u32::try_from(timestamp).is_err() {
+        return Err(ChunkError::TimestampOutOfRange(timestamp));
+    }
+    return Ok(timestamp);
+}
+
+/// How instruction operands are laid out after their opcode byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OperandEncoding {
+    /// Every operand is a 4-byte little-endian i32.
+    #[default]
+    Fixed,
+    /// Every operand is a zigzag-encoded LEB128 varint of 1 to 5 bytes.
+    Compact
+}
+
+impl OperandEncoding {
+    /// The header flag bits that mark a chunk as using this encoding.
+    pub fn header_flag(self) -> u8 {
+        match self {
+            OperandEncoding::Fixed => 0,
+            OperandEncoding::Compact => header_flag_const::COMPACT_OPERANDS
+        }
+    }
+}
+
+/// A single decoded instruction and where it sits in its chunk.
+pub struct Instruction<'a> {
+    pub opcode: &'a OpCode<'a>,
+    pub args: Vec<Argument>,
+    pub offset: ChunkOffset,
+    pub next_offset: ChunkOffset
+}
+
+/// Errors raised while reading, writing or decoding a chunk.
+#[derive(Debug)]
+pub enum ChunkError {
+    /// Reading the chunk file at `path` failed.
+    Read { path: String, source: io::Error },
+    /// Writing the chunk file at `path` failed.
+    Write { path: String, source: io::Error },
+    /// The chunk ended before `needed` bytes could be decoded at `offset`.
+    UnexpectedEnd { offset: ChunkOffset, needed: usize, len: usize },
+    /// The byte at `offset` is not a known opcode.
+    UnknownOpcode { offset: ChunkOffset, byte: u8 },
+    /// The varint operand at `offset` does not fit in an argument.
+    OperandOverflow { offset: ChunkOffset },
+    /// The chunk is a relocatable object, which must be linked before it can run.
+    NotExecutable,
+    /// The chunk is not a relocatable object with fixed-width operands, so it cannot be linked.
+    NotRelocatable,
+    /// An object chunk was requested with compact operands, which the linker cannot patch.
+    CompactObject,
+    /// Section `section` holds an entry that cannot be decoded at `offset`.
+    MalformedSection { section: u8, offset: ChunkOffset },
+    /// The branch at `offset` targets `target`, which is inside another instruction.
+    MisalignedBranch { offset: ChunkOffset, target: ChunkOffset },
+    /// A header timestamp is negative or does not fit in 32 bits.
+    TimestampOutOfRange(i64),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::Read { path, source } => write!(f, "Failed to read chunk file {}: {}", path, source),
+            ChunkError::Write { path, source } => write!(f, "Failed to write chunk file {}: {}", path, source),
+            ChunkError::UnexpectedEnd { offset, needed, len } => write!(
+                f,
+                "Unexpected end of chunk at offset {}: needed {} bytes but chunk is {} bytes long",
+                offset, needed, len
+            ),
+            ChunkError::UnknownOpcode { offset, byte } => write!(
+                f,
+                "Unknown opcode with value 0x{:02x} at offset {}", byte, offset
+            ),
+            ChunkError::OperandOverflow { offset } => write!(f, "Operand at offset {} overflows 32 bits", offset),
+            ChunkError::NotExecutable => write!(f, "Chunk is a relocatable object and must be linked before running"),
+            ChunkError::NotRelocatable => write!(f, "Chunk is not a relocatable object with fixed-width operands"),
+            ChunkError::CompactObject => write!(f, "Object chunks require fixed operand encoding"),
+            ChunkError::MalformedSection { section, offset } => write!(
+                f,
+                "Malformed entry in section 0x{:02x} at offset {}", section, offset
+            ),
+            ChunkError::MisalignedBranch { offset, target } => write!(
+                f,
+                "Branch at offset {} targets offset {}, which is not the start of an instruction", offset, target
+            ),
+            ChunkError::TimestampOutOfRange(timestamp) => write!(
+                f,
+                "Timestamp {} does not fit in a header, which holds 0 to {}", timestamp, u32::MAX
+            ),
+        }
+    }
+}
+
+impl Error for ChunkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChunkError::Read { source, .. } | ChunkError::Write { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 #[allow(unused)]
-pub trait Chunk {
-    fn from_file(path: &str) -> Self;
-    fn to_file(&self, path: &str);
+pub trait Chunk: Sized {
+    fn from_file(path: &str) -> Result<Self, ChunkError>;
+    fn to_file(&self, path: &str) -> Result<(), ChunkError>;
     fn write_byte(&mut self, byte: u8);
     fn write_op(&mut self, opcode: &OpCode);
     fn write_arg(&mut self, arg: StackPointer);
-    fn read_arg(&self, offset: ChunkOffset) -> StackPointer;
-    fn with_header(&mut self, assembly: bool) -> Self;
-    fn without_header(&self) -> Self;
+    fn read_arg(&self, offset: ChunkOffset) -> Result<StackPointer, ChunkError>;
+    fn write_operand(&mut self, arg: Argument, encoding: OperandEncoding);
+    fn read_operand(&self, offset: ChunkOffset, encoding: OperandEncoding) -> Result<(Argument, usize), ChunkError>;
+    fn read_instruction<'a>(&self, offset: ChunkOffset, encoding: OperandEncoding, lookup: &'a OpCodeLookup<'a>) -> Result<Instruction<'a>, ChunkError>;
+    fn read_op(&self, offset: ChunkOffset, encoding: OperandEncoding, lookup: &OpCodeLookup) -> Result<(Op, ChunkOffset), ChunkError>;
+    fn with_header(&mut self, flags: u8, timestamp: i64) -> Self;
+    fn without_header(&self) -> Result<Self, ChunkError>;
     fn verify_possible_header(&self) -> bool;
+    fn header_flags(&self) -> u8;
+    fn operand_encoding(&self) -> OperandEncoding;
 }
 
 impl Chunk for Vec<u8> {
-    fn from_file(path: &str) -> Self {
-        let result = std::fs::read(path);
-        match result {
-            Ok(vec) => vec,
-            Err(_) => panic!("Failed to read chunk file")
-        }
+    fn from_file(path: &str) -> Result<Self, ChunkError> {
+        std::fs::read(path).map_err(|source| ChunkError::Read { path: path.to_string(), source })
     }
 
-    fn to_file(&self, path: &str) {
-        let result = std::fs::write(path, self);
-        match result {
-            Ok(_) => {},
-            Err(_) => panic!("Failed to write chunk file")
-        }
+    fn to_file(&self, path: &str) -> Result<(), ChunkError> {
+        std::fs::write(path, self).map_err(|source| ChunkError::Write { path: path.to_string(), source })
     }
 
     fn write_byte(&mut self, byte: u8) {
@@ -69,31 +184,115 @@ impl Chunk for Vec<u8> {
         self.write_byte((arg >> 24) as u8);
     }
 
-    fn read_arg(&self, offset: ChunkOffset) -> Argument {
-        self[offset] as Argument
-        | (self[offset+1] as Argument) << 8
-        | (self[offset+2] as Argument) << 16
-        | (self[offset+3] as Argument) << 24
+    fn read_arg(&self, offset: ChunkOffset) -> Result<Argument, ChunkError> {
+        let needed = (Argument::BITS / u8::BITS) as usize;
+        if offset + needed > self.len() {
+            return Err(ChunkError::UnexpectedEnd { offset, needed, len: self.len() });
+        }
+        Ok(self[offset] as Argument
+            | (self[offset+1] as Argument) << 8
+            | (self[offset+2] as Argument) << 16
+            | (self[offset+3] as Argument) << 24)
+    }
+
+    fn write_operand(&mut self, arg: Argument, encoding: OperandEncoding) {
+        match encoding {
+            OperandEncoding::Fixed => self.write_arg(arg),
+            OperandEncoding::Compact => {
+                // zigzag maps small magnitudes of either sign to small unsigned values
+                let mut zigzag = ((arg << 1) ^ (arg >> 31)) as u32;
+                while zigzag >= 0x80 {
+                    self.write_byte((zigzag as u8) | 0x80);
+                    zigzag >>= 7;
+                }
+                self.write_byte(zigzag as u8);
+            }
+        }
+    }
+
+    /// Reads one operand, returning it along with the number of bytes it took up.
+    fn read_operand(&self, offset: ChunkOffset, encoding: OperandEncoding) -> Result<(Argument, usize), ChunkError> {
+        match encoding {
+            OperandEncoding::Fixed => Ok((self.read_arg(offset)?, (Argument::BITS / u8::BITS) as usize)),
+            OperandEncoding::Compact => {
+                let mut zigzag: u32 = 0;
+                let mut size = 0;
+                loop {
+                    let Some(&byte) = self.get(offset + size) else {
+                        return Err(ChunkError::UnexpectedEnd { offset, needed: size + 1, len: self.len() });
+                    };
+                    let bits = (byte & 0x7f) as u32;
+                    let shift = 7 * size as u32;
+                    if shift >= u32::BITS || (bits << shift) >> shift != bits {
+                        return Err(ChunkError::OperandOverflow { offset });
+                    }
+                    zigzag |= bits << shift;
+                    size += 1;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                let arg = ((zigzag >> 1) as Argument) ^ -((zigzag & 1) as Argument);
+                Ok((arg, size))
+            }
+        }
+    }
+
+    /// Decodes the opcode and operands starting at offset. This is the one place
+    /// that knows how instructions are laid out; everything that walks a chunk
+    /// should go through it.
+    fn read_instruction<'a>(&self, offset: ChunkOffset, encoding: OperandEncoding, lookup: &'a OpCodeLookup<'a>) -> Result<Instruction<'a>, ChunkError> {
+        let Some(&byte) = self.get(offset) else {
+            return Err(ChunkError::UnexpectedEnd { offset, needed: 1, len: self.len() });
+        };
+        let opcode = lookup.from_byte(byte).ok_or(ChunkError::UnknownOpcode { offset, byte })?;
+
+        let mut args = Vec::with_capacity(opcode.num_params);
+        let mut next_offset = offset + 1;
+        for _ in 0..opcode.num_params {
+            let (arg, size) = self.read_operand(next_offset, encoding)?;
+            args.push(arg);
+            next_offset += size;
+        }
+
+        Ok(Instruction { opcode, args, offset, next_offset })
+    }
+
+    /// Decodes the instruction at offset like `read_instruction`, but into the
+    /// VM's allocation-free form. Returns it and the offset of the next instruction.
+    fn read_op(&self, offset: ChunkOffset, encoding: OperandEncoding, lookup: &OpCodeLookup) -> Result<(Op, ChunkOffset), ChunkError> {
+        let Some(&byte) = self.get(offset) else {
+            return Err(ChunkError::UnexpectedEnd { offset, needed: 1, len: self.len() });
+        };
+        let opcode = lookup.from_byte(byte).ok_or(ChunkError::UnknownOpcode { offset, byte })?;
+
+        let mut args = [0; MAX_OPERANDS];
+        let mut next_offset = offset + 1;
+        for arg in args.iter_mut().take(opcode.num_params) {
+            let (value, size) = self.read_operand(next_offset, encoding)?;
+            *arg = value;
+            next_offset += size;
+        }
+
+        Ok(((opcode.decode)(args), next_offset))
     }
     
     /// If chunk needs a header, adds one and leaves the original chunk empty.
     /// Otherwise returns a clone of itself.
-    fn with_header(&mut self, is_assembly: bool) -> Self {
+    /// `flags` is a combination of `header_flag_const` bits, and `timestamp` is
+    /// usually from `resolve_timestamp`.
+    fn with_header(&mut self, flags: u8, timestamp: i64) -> Self {
         if self.verify_possible_header() {
             return self.to_vec();
         }
         let (major, minor, patch) = crate::version();
-        let unix_seconds = Utc::now().timestamp();
+        let unix_seconds = timestamp;
         let (time_0, time_1, time_2, time_3) = (
             unix_seconds as u8,
             (unix_seconds >> 8) as u8,
             (unix_seconds >> 16) as u8,
             (unix_seconds >> 24) as u8
         );
-        let mut flags: u8 = 0x0;
-        if is_assembly { // only use first bit for now to indicate bytecode source
-            flags |= 0b1;
-        }
 
         let mut header: Vec<u8> = vec![
                 CCIL_MAGIC_BYTE_0, CCIL_MAGIC_BYTE_1, // 0-1 magic number
@@ -109,17 +308,41 @@ impl Chunk for Vec<u8> {
         return header;
     }
     
-    /// Removes the header, if one exists.
-    fn without_header(&self) -> Self {
+    /// Removes the header, if one exists. A chunk that starts with the magic
+    /// number but is too short to hold a full header is an error.
+    fn without_header(&self) -> Result<Self, ChunkError> {
+        if self.len() >= 2 && self[0] == CCIL_MAGIC_BYTE_0 && self[1] == CCIL_MAGIC_BYTE_1
+            && self.len() < BYTECODE_HEADER_SIZE {
+            return Err(ChunkError::UnexpectedEnd { offset: 0, needed: BYTECODE_HEADER_SIZE, len: self.len() });
+        }
         if !self.verify_possible_header() {
-            return self.to_vec();
+            return Ok(self.to_vec());
         }
-        return self[BYTECODE_HEADER_SIZE..].to_vec();
+        return Ok(self[BYTECODE_HEADER_SIZE..].to_vec());
     }
     
     /// Checks that a header could exist at the beginning of the chunk
     /// (checks magic number and length), does not guarantee that it's a header.
     fn verify_possible_header(&self) -> bool {
-        self.len() >= 16 && self[0] == 0xCC && self[1] == 0x17
+        self.len() >= BYTECODE_HEADER_SIZE && self[0] == CCIL_MAGIC_BYTE_0 && self[1] == CCIL_MAGIC_BYTE_1
+    }
+
+    /// Reads the header flags byte, or 0 if there is no header.
+    fn header_flags(&self) -> u8 {
+        if self.verify_possible_header() {
+            self[BYTECODE_HEADER_FLAGS_OFFSET]
+        } else {
+            0
+        }
+    }
+
+    /// Reads the operand encoding from the header flags. Headerless chunks
+    /// always use the fixed encoding.
+    fn operand_encoding(&self) -> OperandEncoding {
+        if self.header_flags() & header_flag_const::COMPACT_OPERANDS != 0 {
+            OperandEncoding::Compact
+        } else {
+            OperandEncoding::Fixed
+        }
     }
 }
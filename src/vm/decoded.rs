@@ -0,0 +1,79 @@
+/*
+vm/decoded.rs: Chunks decoded ahead of execution
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::vm::chunk::{Chunk, ChunkError, ChunkOffset, OperandEncoding};
+use crate::vm::opcode::{Argument, Op, OpCodeLookup};
+
+/// A headerless chunk decoded once before it runs, so executing it never
+/// touches the bytes again. Branch targets of JUMP, IFZ, IFNZ and CALL are
+/// instruction indices rather than byte offsets; a target of `ops.len()`
+/// leaves the chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedChunk {
+    pub ops: Vec<Op>,
+    /// The byte offset of each op, followed by the length of the chunk.
+    pub offsets: Vec<ChunkOffset>
+}
+
+impl DecodedChunk {
+    /// Decodes every instruction in a chunk and resolves its branch targets.
+    /// Branches past the end of the chunk leave it, as they would when run
+    /// from bytes, but branches into the middle of an instruction are errors.
+    pub fn decode(chunk: &Vec<u8>, encoding: OperandEncoding, lookup: &OpCodeLookup) -> Result<Self, ChunkError> {
+        let mut ops = Vec::<Op>::new();
+        let mut offsets = Vec::<ChunkOffset>::new();
+        let mut offset = 0;
+        while offset < chunk.len() {
+            let (op, next_offset) = chunk.read_op(offset, encoding, lookup)?;
+            ops.push(op);
+            offsets.push(offset);
+            offset = next_offset;
+        }
+        offsets.push(chunk.len());
+
+        for (index, op) in ops.iter_mut().enumerate() {
+            if let Op::Jump(target) | Op::Ifz(target) | Op::Ifnz(target) | Op::CopyIfz(target) | Op::CopyIfnz(target) | Op::Call(target) = op {
+                let Some(resolved) = index_of(&offsets, *target as ChunkOffset) else {
+                    return Err(ChunkError::MisalignedBranch { offset: offsets[index], target: *target as ChunkOffset });
+                };
+                *target = resolved as Argument;
+            }
+        }
+        return Ok(Self { ops, offsets });
+    }
+
+    /// The instruction starting at a byte offset, `ops.len()` for offsets at
+    /// or past the end of the chunk, or None for the middle of an instruction.
+    pub fn index_of(&self, offset: ChunkOffset) -> Option<usize> {
+        return index_of(&self.offsets, offset);
+    }
+
+    /// The byte offset of an instruction, or the length of the chunk for
+    /// indices past the last instruction.
+    pub fn offset_of(&self, index: usize) -> ChunkOffset {
+        return self.offsets[index.min(self.ops.len())];
+    }
+}
+
+fn index_of(offsets: &[ChunkOffset], offset: ChunkOffset) -> Option<usize> {
+    let end = offsets.len() - 1;
+    if offset >= offsets[end] {
+        return Some(end);
+    }
+    return offsets.binary_search(&offset).ok();
+}
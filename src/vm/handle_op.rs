@@ -16,463 +16,201 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::collections::hash_map::Entry;
 use std::io::Write;
 
-use rustc_hash::FxHashMap;
-
-use crate::dprintln;
 use crate::vm::VirtualMachine;
-use crate::vm::stack::{VecStack, Stack, StackPointer, StackItem, Shift};
+use crate::vm::stack::{Stack, StackPointer, StackItem};
 use crate::vm::chunk::ChunkOffset;
-use crate::vm::opcode::Argument;
+use crate::vm::decoded::DecodedChunk;
+use crate::vm::memory::VARIABLE_BYTES;
+use crate::vm::opcode::Op;
 use crate::vm::variable_value::VariableValue;
+use crate::compiler::VariableId;
 use crate::constants::{fileno_const, type_id_const};
 
-pub type OpcodeHandler = fn(&mut VirtualMachine, &[Argument], ChunkOffset) -> Result<Option<ChunkOffset>, String>;
-
-const POP_ERROR_STR: &str = "Cannot pop from empty stack";
-
-fn compute_opcode_size(num_args: usize) -> ChunkOffset {
-    1 + num_args * (Argument::BITS as usize) / (u8::BITS as usize)
-}
-
-pub fn handle_nop(_vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    dprintln!("NOP");
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_constant(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let constant = args[0] as StackItem;
-    vm.stack.push(constant);
-    dprintln!("CONST {}", constant);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_pop(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let val = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    dprintln!("POP ({})", val);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_drop(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let count = args[0] as usize;
-    for _ in 0..count {
-        vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    }
-    dprintln!("DROP {}", count);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_copy(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let address = args[0] as StackPointer;
-    let data = vm.stack.get(address);
-    vm.stack.push(data);
-    dprintln!("COPY {} ({})", address, data);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_store(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 2);
-
-    let variable_id = args[0];
-    let type_id = args[1];
-    let data = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    
-    let value = match type_id {
-        type_id_const::STRING => VariableValue::StringLiteral(data as usize),
-        type_id_const::NUMBER => VariableValue::Number(data),
-        type_id_const::FLOAT => todo!(),
-        type_id_const::NULL => VariableValue::Null,
-        type_id_const::BOOLEAN => VariableValue::Boolean(data != 0),
-        type_id_const::UNKNOWN => todo!(), // add runtime type inference here
-        _ => todo!()
-    };
-    vm.variables.insert(variable_id, value);
-
-    dprintln!("STORE {} {} ({})", variable_id, type_id, data);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_load(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let variable_id = &args[0];
-    let value = match vm.variables.get(variable_id) {
-        Some(val) => val,
-        None => panic!("Attempted to access valueless variable")
-    };
-
-    match value {
-        VariableValue::StringLiteral(val) => vm.stack.push(*val as i32),
-        VariableValue::Number(val) => vm.stack.push(*val),
-        VariableValue::Float(_) => todo!(),
-        VariableValue::Null => vm.stack.push(0),
-        VariableValue::Boolean(val) => {
-            if *val {
-                vm.stack.push(1)
-            } else {
-                vm.stack.push(0)
+impl VirtualMachine<'_, '_> {
+    /// Executes the instruction at an index of a decoded chunk, and returns
+    /// the index to continue at (None to exit).
+    #[inline]
+    pub(crate) fn step(&mut self, program: &DecodedChunk, index: usize) -> Result<Option<usize>, String> {
+        let op = program.ops[index];
+        match op {
+            Op::Nop => {},
+            Op::Const(constant) => self.stack.push(constant as StackItem)?,
+            Op::Pop => { self.stack.pop()?; },
+            Op::Drop(count) => {
+                for _ in 0..count as usize {
+                    self.stack.pop()?;
+                }
+            },
+            Op::Copy(address) => {
+                let data = self.stack.get(address as StackPointer)?;
+                self.stack.push(data)?;
+            },
+            Op::Store(variable_id, type_id) => {
+                let data = self.stack.pop()?;
+                let value = match type_id {
+                    type_id_const::STRING => VariableValue::StringLiteral(data as usize),
+                    type_id_const::NUMBER => VariableValue::Number(data),
+                    type_id_const::NULL => VariableValue::Null,
+                    type_id_const::BOOLEAN => VariableValue::Boolean(data != 0),
+                    // floats and runtime type inference (UNKNOWN) are not implemented yet
+                    _ => return Err(format!("Cannot store a value with type id {}", type_id))
+                };
+                self.store(variable_id, value)?;
+            },
+            Op::Load(variable_id) => {
+                let item = self.load(variable_id)?;
+                self.stack.push(item)?;
+            },
+            Op::IncVar(variable_id, amount) => {
+                let item = self.load(variable_id)?;
+                self.store(variable_id, VariableValue::Number(self.arithmetic.add(item, amount as StackItem)?))?;
+            },
+            Op::Swap => {
+                let b = self.stack.pop()?;
+                let a = self.stack.pop()?;
+                self.stack.push(b)?;
+                self.stack.push(a)?;
+            },
+            Op::Rot(count) => {
+                let item_moving_down = self.stack.pop()?;
+                self.stack.insert(count as StackPointer, item_moving_down)?;
+            },
+            Op::Neg => {
+                let val = self.stack.pop()?;
+                self.stack.push(self.arithmetic.neg(val)?)?;
+            },
+            Op::BNot => {
+                let val = self.stack.pop()?;
+                self.stack.push(!val)?;
+            },
+            Op::AddC(constant) => {
+                let val = self.stack.pop()?;
+                self.stack.push(self.arithmetic.add(val, constant as StackItem)?)?;
+            },
+            Op::Not => {
+                let val = self.stack.pop()? != 0;
+                self.stack.push(!val as StackItem)?;
+            },
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::BOr | Op::BAnd | Op::BXor
+            | Op::Or | Op::And | Op::Xor | Op::Shl | Op::Shrl | Op::Shra => {
+                // binary operators pop their right operand first (`a, b -> a op b`)
+                let b = self.stack.pop()?;
+                let a = self.stack.pop()?;
+                let result = match op {
+                    Op::Add => self.arithmetic.add(a, b)?,
+                    Op::Sub => self.arithmetic.sub(a, b)?,
+                    Op::Mul => self.arithmetic.mul(a, b)?,
+                    Op::Div => self.arithmetic.div(a, b)?,
+                    Op::Mod => self.arithmetic.rem(a, b)?,
+                    Op::BOr => a | b,
+                    Op::BAnd => a & b,
+                    Op::BXor => a ^ b,
+                    Op::Or => ((a != 0) || (b != 0)) as StackItem,
+                    Op::And => ((a != 0) && (b != 0)) as StackItem,
+                    Op::Xor => ((a != 0) != (b != 0)) as StackItem,
+                    Op::Shl => self.arithmetic.shl(a, b)?,
+                    Op::Shrl => self.arithmetic.shrl(a, b)?,
+                    Op::Shra => self.arithmetic.shra(a, b)?,
+                    _ => unreachable!()
+                };
+                self.stack.push(result)?;
+            },
+            Op::Jump(target) => return Ok(Some(target as usize)),
+            Op::Ifz(target) => {
+                if self.stack.pop()? == 0 {
+                    return Ok(Some(target as usize));
+                }
+            },
+            Op::Ifnz(target) => {
+                if self.stack.pop()? != 0 {
+                    return Ok(Some(target as usize));
+                }
+            },
+            Op::CopyIfz(target) => {
+                if self.stack.peek()? == 0 {
+                    return Ok(Some(target as usize));
+                }
+            },
+            Op::CopyIfnz(target) => {
+                if self.stack.peek()? != 0 {
+                    return Ok(Some(target as usize));
+                }
+            },
+            Op::Call(target) => {
+                // return addresses stay byte offsets, as the program sees them
+                self.stack.push(program.offset_of(index + 1) as StackItem)?;
+                return Ok(Some(target as usize));
+            },
+            Op::Return(discard_count) => {
+                for _ in 0..discard_count as usize {
+                    self.stack.pop()?;
+                }
+                let return_address = self.stack.pop()?;
+                return match ChunkOffset::try_from(return_address).ok().and_then(|offset| program.index_of(offset)) {
+                    Some(target) => Ok(Some(target)),
+                    None => Err(format!("Return address {} is not the start of an instruction", return_address))
+                };
+            },
+            Op::Write(fileno) => {
+                let value = self.stack.pop()?;
+                self.write_to(fileno, &value.to_string())?;
+            },
+            Op::WriteS(fileno) => {
+                let string_pointer = self.stack.pop()? as usize;
+                let write_string = self.get_string(string_pointer)?;
+                self.write_to(fileno, &write_string)?;
             }
-        },
-    }
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_swap(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    vm.stack.push(b);
-    vm.stack.push(a);
-    dprintln!("SWAP {} {} -> {} {}", a, b, b, a);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_rot(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let count = args[0] as StackPointer;
-
-    let item_moving_down = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    vm.stack.insert(count, item_moving_down);
-    dprintln!("ROT {}", count);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_neg(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let val = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let negative = -val;
-    vm.stack.push(negative);
-    dprintln!("NEG {} -> {}", val, negative);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_add(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let sum = a + b;
-    vm.stack.push(sum);
-    dprintln!("ADD {} {} -> {}", a, b, sum);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_sub(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let difference = a - b;
-    vm.stack.push(difference);
-    dprintln!("SUB {} {} -> {}", a, b, difference);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_mul(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let product = a - b;
-    vm.stack.push(product);
-    dprintln!("MUL {} {} -> {}", a, b, product);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_div(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let divisor = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let dividend = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let quotient = dividend / divisor;
-    vm.stack.push(quotient);
-    dprintln!("DIV {} {} -> {}", dividend, divisor, quotient);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_mod(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let divisor = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let dividend = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let remainder = dividend % divisor;
-    vm.stack.push(remainder);
-    dprintln!("MOD {} {} -> {}", dividend, divisor, remainder);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_bnot(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let val = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let bitwise_not = !val;
-    vm.stack.push(bitwise_not);
-    dprintln!("BNOT {} -> {}", val, bitwise_not);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_bor(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let bitwise_or = a | b;
-    vm.stack.push(bitwise_or);
-    dprintln!("BOR {} {} -> {}", a, b, bitwise_or);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_band(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let bitwise_and = a & b;
-    vm.stack.push(bitwise_and);
-    dprintln!("BOR {} {} -> {}", a, b, bitwise_and);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_bxor(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let bitwise_xor = a ^ b;
-    vm.stack.push(bitwise_xor);
-    dprintln!("BXOR {} {} -> {}", a, b, bitwise_xor);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_not(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let val = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let boolean_not = !val;
-    vm.stack.push(boolean_not as StackItem);
-    dprintln!("NOT {} -> {}", val, boolean_not);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_or(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let boolean_or = a || b;
-    vm.stack.push(boolean_or as StackItem);
-    dprintln!("OR {} {} -> {}", a, b, boolean_or);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_and(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let boolean_and = a && b;
-    vm.stack.push(boolean_and as StackItem);
-    dprintln!("AND {} {} -> {}", a, b, boolean_and);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_xor(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let b = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let a = vm.stack.pop().ok_or(POP_ERROR_STR)? != 0;
-    let boolean_xor = (a && !b) || (!a && b);
-    vm.stack.push(boolean_xor as StackItem);
-    dprintln!("XOR {} {} -> {}", a, b, boolean_xor);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_shl(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let shift_amount = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let value = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let shifted = shift_amount << value;
-    vm.stack.push(shifted as StackItem);
-    dprintln!("SHL {} {} -> {}", value, shift_amount, shifted);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_shrl(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let shift_amount = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let value = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let shifted = value.logical_shift(shift_amount);
-    vm.stack.push(shifted as StackItem);
-    dprintln!("SHRL {} {} -> {}", value, shift_amount, shifted);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_shra(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    let shift_amount = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let value = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    let shifted = value.logical_shift(shift_amount);
-    vm.stack.push(shifted as StackItem);
-    dprintln!("SHRA {} {} -> {}", value, shift_amount, shifted);
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_jump(_vm: &mut VirtualMachine, args: &[Argument], _offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let address = args[0] as ChunkOffset;
-    dprintln!("JUMP {}", address);
-
-    Ok(Some(address))
-}
-
-pub fn handle_ifz(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let address = args[0] as ChunkOffset;
-    let condition = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    dprintln!("IFZ {} ({})", address, condition);
-
-    if condition == 0 {
-        Ok(Some(address))
-    } else {
-        Ok(Some(offset + compute_opcode_size(args.len())))
-    }
-
-}
-
-pub fn handle_ifnz(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let address = args[0] as ChunkOffset;
-    let condition = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    dprintln!("IFNZ {} ({})", address, condition);
-
-    if condition != 0 {
-        Ok(Some(address))
-    } else {
-        Ok(Some(offset + compute_opcode_size(args.len())))
-    }
-
-}
-
-pub fn handle_call(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let call_address = args[0] as ChunkOffset;
-    let return_address = offset + compute_opcode_size(args.len());
-    vm.stack.push(return_address as StackItem);
-    dprintln!("CALL {}", call_address);
-
-    Ok(Some(call_address))
-}
-
-pub fn handle_return(vm: &mut VirtualMachine, args: &[Argument], _offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let discard_count = args[0] as usize;
-    for _ in 0..discard_count {
-        vm.stack.pop().ok_or(POP_ERROR_STR)?;
+        }
+        Ok(Some(index + 1))
     }
 
-    let return_address = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-    assert!(return_address >= 0, "Return address must be non-negative");
-
-    dprintln!("RETURN {} -> ({})", discard_count, return_address);
-
-    Ok(Some(return_address as ChunkOffset))
-}
-
-pub fn handle_exit(args: &[Argument], _offset: ChunkOffset, _stack: &mut VecStack, _variables: &mut FxHashMap<i32, VariableValue>) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 0);
-
-    dprintln!("EXIT");
-
-    Ok(None)
-}
-
-pub fn handle_write(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let fileno = args[0];
-    let value = vm.stack.pop().ok_or(POP_ERROR_STR)?;
-
-    dprintln!("WRITE {}", fileno);
-
-    match fileno {
-        fileno_const::STDIN => panic!("Cannot write to STDIN"),
-        fileno_const::STDOUT => println!("{}", value),
-        fileno_const::STDERR => eprintln!("{}", value),
-        other_value => {
-            let mut file = &vm.opened_files[other_value as usize - 2];
-            let _ = write!(file, "{}", value);
+    /// Sets a variable, charging for it the first time it is stored to.
+    #[inline]
+    fn store(&mut self, variable_id: VariableId, value: VariableValue) -> Result<(), String> {
+        match self.variables.entry(variable_id) {
+            Entry::Occupied(mut entry) => { entry.insert(value); },
+            Entry::Vacant(entry) => {
+                self.memory.charge(VARIABLE_BYTES)?;
+                entry.insert(value);
+            }
         }
+        Ok(())
     }
 
-    Ok(Some(offset + compute_opcode_size(args.len())))
-}
-
-pub fn handle_writes(vm: &mut VirtualMachine, args: &[Argument], offset: ChunkOffset) -> Result<Option<ChunkOffset>, String> {
-    assert_eq!(args.len(), 1);
-
-    let fileno = args[0];
-    let string_pointer = vm.stack.pop().ok_or(POP_ERROR_STR)? as usize;
-    let write_string = vm.get_string(string_pointer);
+    /// The value of a variable as a stack item.
+    fn load(&self, variable_id: VariableId) -> Result<StackItem, String> {
+        let Some(value) = self.variables.get(&variable_id) else {
+            return Err(format!("Attempted to access valueless variable {}", variable_id));
+        };
+        return match value {
+            VariableValue::StringLiteral(val) => Ok(*val as StackItem),
+            VariableValue::Number(val) => Ok(*val),
+            VariableValue::Float(_) => Err(format!("Cannot load float variable {}", variable_id)),
+            VariableValue::Null => Ok(0),
+            VariableValue::Boolean(val) => Ok(*val as StackItem)
+        };
+    }
 
-    dprintln!("WRITES {}", fileno);
-    
-    match fileno {
-        fileno_const::STDIN => panic!("Cannot write to STDIN"),
-        fileno_const::STDOUT => println!("{}", write_string),
-        fileno_const::STDERR => eprintln!("{}", write_string),
-        other_value => {
-            let mut file = &vm.opened_files[other_value as usize - 2];
-            let _ = write!(file, "{}", write_string);
+    fn write_to(&self, fileno: i32, text: &str) -> Result<(), String> {
+        match fileno {
+            fileno_const::STDIN => return Err("Cannot write to STDIN".to_string()),
+            fileno_const::STDOUT => println!("{}", text),
+            fileno_const::STDERR => eprintln!("{}", text),
+            other_value => {
+                // opened files are numbered after STDERR
+                let opened = match other_value > fileno_const::STDERR {
+                    true => self.opened_files.get(other_value as usize - 2),
+                    false => None
+                };
+                let Some(mut file) = opened else {
+                    return Err(format!("Cannot write to file {}", other_value));
+                };
+                let _ = write!(file, "{}", text);
+            }
         }
+        Ok(())
     }
-
-    Ok(Some(offset + compute_opcode_size(args.len())))
 }
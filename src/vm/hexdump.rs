@@ -0,0 +1,252 @@
+/*
+vm/hexdump.rs: Annotated hex dumps of CCIL bytecode files
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{TimeZone, Utc};
+
+use crate::constants::{BYTECODE_HEADER_SIZE, header_flag_const};
+use crate::linker::relocation_id_const;
+use crate::vm::chunk::{Chunk, ChunkOffset, OperandEncoding};
+use crate::vm::opcode::OpCodeLookup;
+use crate::vm::section::{read_name, section_id_const, symbol_id_const};
+
+// inner width of a box border, and the column annotations start at
+const BOX_WIDTH: usize = 40;
+const COMMENT_COLUMN: usize = 29;
+// raw data is wrapped to this many bytes per line
+const DATA_ROW_BYTES: usize = 8;
+
+/// Splits the section entry at an offset into the ends of its fields, and describes it.
+type EntryReader = fn(&Vec<u8>, ChunkOffset) -> Option<(Vec<ChunkOffset>, String)>;
+
+/// Writes a file out as hex, one field or instruction per line, each annotated
+/// with what it means. Parts that cannot be decoded are dumped as raw bytes,
+/// so any file can be inspected, even a corrupt one.
+pub fn hexdump(file: &Vec<u8>, lookup: &OpCodeLookup) -> String {
+    let mut out = String::new();
+    if !file.verify_possible_header() {
+        open_box(&mut out, "PROGRAM");
+        dump_code(&mut out, file, OperandEncoding::Fixed, lookup);
+        close_box(&mut out);
+        return out;
+    }
+
+    let flags = file.header_flags();
+    let timestamp = u32::from_le_bytes([file[6], file[7], file[8], file[9]]) as i64;
+    let date = match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(date) => date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => "invalid".to_string()
+    };
+    open_box(&mut out, "HEADER");
+    row(&mut out, &[&file[0..1], &file[1..2]], "magic num");
+    row(&mut out, &[&file[2..3], &file[3..4], &file[4..5]], &format!("v{}.{}.{}", file[2], file[3], file[4]));
+    row(&mut out, &[&file[5..6]], &describe_flags(flags));
+    row(&mut out, &[&file[6..7], &file[7..8], &file[8..9], &file[9..10]], &format!("timestamp {}", date));
+    let padding: Vec<&[u8]> = file[10..BYTECODE_HEADER_SIZE].chunks(1).collect();
+    row(&mut out, &padding, "padding");
+    close_box(&mut out);
+
+    let body = &file[BYTECODE_HEADER_SIZE..];
+    let encoding = file.operand_encoding();
+    if flags & header_flag_const::SECTIONED == 0 {
+        open_box(&mut out, "PROGRAM");
+        dump_code(&mut out, body, encoding, lookup);
+        close_box(&mut out);
+        return out;
+    }
+
+    let mut offset = 0;
+    while offset < body.len() {
+        let id = body[offset];
+        let Some(len) = body.get(offset + 1..offset + 5).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize) else {
+            open_box(&mut out, "TRAILING BYTES");
+            dump_data(&mut out, &body[offset..], "truncated section");
+            close_box(&mut out);
+            break;
+        };
+        let start = offset + 5;
+        let end = start.saturating_add(len).min(body.len());
+        let data = &body[start..end];
+        let name = section_name(id);
+        open_box(&mut out, &name);
+        let truncated = if end - start < len { ", truncated" } else { "" };
+        row(&mut out, &[&body[offset..offset + 1], &body[offset + 1..start]], &format!("section {:02X}, {} bytes{}", id, len, truncated));
+        match id {
+            section_id_const::CODE => dump_code(&mut out, data, encoding, lookup),
+            section_id_const::STRINGS => dump_strings(&mut out, data),
+            section_id_const::EXPORTS => dump_entries(&mut out, data, dump_export),
+            section_id_const::RELOCATIONS => dump_entries(&mut out, data, dump_relocation),
+            section_id_const::SYMBOLS => dump_entries(&mut out, data, dump_symbol),
+            _ => dump_data(&mut out, data, "unknown section data")
+        }
+        close_box(&mut out);
+        offset = end;
+    }
+    return out;
+}
+
+fn section_name(id: u8) -> String {
+    match id {
+        section_id_const::CODE => "CODE".to_string(),
+        section_id_const::STRINGS => "STRINGS".to_string(),
+        section_id_const::EXPORTS => "EXPORTS".to_string(),
+        section_id_const::RELOCATIONS => "RELOCATIONS".to_string(),
+        section_id_const::SYMBOLS => "SYMBOLS".to_string(),
+        id => format!("SECTION {:02X}", id)
+    }
+}
+
+fn describe_flags(flags: u8) -> String {
+    let mut names = Vec::<&str>::new();
+    names.push(if flags & header_flag_const::FROM_ASSEMBLY != 0 { "assembly" } else { "source" });
+    for (bit, name) in [
+        (header_flag_const::COMPACT_OPERANDS, "compact"),
+        (header_flag_const::SECTIONED, "sectioned"),
+        (header_flag_const::RELOCATABLE, "relocatable")
+    ] {
+        if flags & bit != 0 {
+            names.push(name);
+        }
+    }
+    return format!("bitflags: {}", names.join(", "));
+}
+
+fn open_box(out: &mut String, title: &str) {
+    let title = format!(" {} ", title);
+    let left = BOX_WIDTH.saturating_sub(title.len()).div_ceil(2);
+    let right = BOX_WIDTH.saturating_sub(title.len() + left);
+    *out += &format!(".{}{}{}.\n", "=".repeat(left), title, "=".repeat(right));
+}
+
+fn close_box(out: &mut String) {
+    *out += &format!("'{}'\n", "=".repeat(BOX_WIDTH));
+}
+
+/// Writes one line: each field as contiguous hex, fields separated by spaces.
+fn row(out: &mut String, fields: &[&[u8]], comment: &str) {
+    let hex: Vec<String> = fields.iter()
+        .map(|field| field.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+        .collect();
+    let hex = hex.join(" ");
+    *out += &format!("{:<width$} -- {}\n", hex, comment, width = COMMENT_COLUMN - 1);
+}
+
+/// Writes raw bytes a few to a line, with the comment on the first line.
+fn dump_data(out: &mut String, data: &[u8], comment: &str) {
+    for (i, line) in data.chunks(DATA_ROW_BYTES).enumerate() {
+        let fields: Vec<&[u8]> = line.chunks(1).collect();
+        row(out, &fields, if i == 0 { comment } else { "" });
+    }
+}
+
+/// Writes one instruction per line, with its offset within the code.
+fn dump_code(out: &mut String, code: &[u8], encoding: OperandEncoding, lookup: &OpCodeLookup) {
+    let code = code.to_vec();
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = match code.read_instruction(offset, encoding, lookup) {
+            Ok(val) => val,
+            Err(error) => {
+                dump_data(out, &code[offset..], &format!("{}", error));
+                return;
+            }
+        };
+        let mut fields: Vec<&[u8]> = vec![&code[offset..offset + 1]];
+        let mut arg_offset = offset + 1;
+        for _ in &instruction.args {
+            // operands are already known to decode, only their sizes are needed
+            let (_, size) = code.read_operand(arg_offset, encoding).unwrap();
+            fields.push(&code[arg_offset..arg_offset + size]);
+            arg_offset += size;
+        }
+        let mut text = instruction.opcode.symbol.to_lowercase();
+        for arg in &instruction.args {
+            text += &format!(" {}", arg);
+        }
+        row(out, &fields, &format!("{:04}: {}", offset, text));
+        offset = instruction.next_offset;
+    }
+}
+
+/// Writes the string pool one null-terminated piece at a time.
+fn dump_strings(out: &mut String, pool: &[u8]) {
+    let mut start = 0;
+    while start < pool.len() {
+        let end = pool[start..].iter().position(|byte| *byte == 0).map(|end| start + end + 1).unwrap_or(pool.len());
+        let text = String::from_utf8_lossy(&pool[start..end]).trim_end_matches('\0').escape_default().to_string();
+        dump_data(out, &pool[start..end], &format!("{:04}: \"{}\"", start, text));
+        start = end;
+    }
+}
+
+/// Writes a section of variable-length entries, one per line, using `entry`
+/// to split and describe each. The rest of the section is dumped raw as soon
+/// as an entry fails to decode.
+fn dump_entries(out: &mut String, data: &[u8], entry: EntryReader) {
+    let data = data.to_vec();
+    let mut offset = 0;
+    while offset < data.len() {
+        let Some((bounds, comment)) = entry(&data, offset) else {
+            dump_data(out, &data[offset..], "malformed entry");
+            return;
+        };
+        let mut fields = Vec::<&[u8]>::new();
+        let mut field_start = offset;
+        for bound in &bounds {
+            fields.push(&data[field_start..*bound]);
+            field_start = *bound;
+        }
+        row(out, &fields, &comment);
+        offset = field_start;
+    }
+}
+
+fn dump_export(data: &Vec<u8>, offset: ChunkOffset) -> Option<(Vec<ChunkOffset>, String)> {
+    let (name, name_end) = read_name(data, section_id_const::EXPORTS, offset).ok()?;
+    let value = data.read_arg(name_end).ok()?;
+    return Some((vec![offset + 4, name_end, name_end + 4], format!("export {} @ {}", name, value)));
+}
+
+fn dump_relocation(data: &Vec<u8>, offset: ChunkOffset) -> Option<(Vec<ChunkOffset>, String)> {
+    let target = data.read_arg(offset + 1).ok()?;
+    let kind = match data[offset] {
+        relocation_id_const::CODE => "code",
+        relocation_id_const::STRING => "string",
+        relocation_id_const::VARIABLE => "variable",
+        relocation_id_const::SYMBOL => {
+            let (name, name_end) = read_name(data, section_id_const::RELOCATIONS, offset + 5).ok()?;
+            return Some((vec![offset + 1, offset + 5, offset + 9, name_end], format!("symbol {} @ {}", name, target)));
+        },
+        _ => return None
+    };
+    return Some((vec![offset + 1, offset + 5], format!("{} @ {}", kind, target)));
+}
+
+fn dump_symbol(data: &Vec<u8>, offset: ChunkOffset) -> Option<(Vec<ChunkOffset>, String)> {
+    let kind = match data[offset] {
+        symbol_id_const::FUNCTION => "function",
+        symbol_id_const::VARIABLE => "variable",
+        _ => return None
+    };
+    let (name, name_end) = read_name(data, section_id_const::SYMBOLS, offset + 1).ok()?;
+    let value = data.read_arg(name_end).ok()?;
+    let comment = match data[offset] {
+        symbol_id_const::FUNCTION => format!("{} {} @ {}", kind, name, value),
+        _ => format!("{} {} = {}", kind, name, value)
+    };
+    return Some((vec![offset + 1, offset + 5, name_end, name_end + 4], comment));
+}
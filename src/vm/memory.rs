@@ -0,0 +1,77 @@
+/*
+vm/memory.rs: Accounting for memory the VM allocates on behalf of a script
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+
+use crate::compiler::VariableId;
+use crate::vm::variable_value::VariableValue;
+
+/// Bytes charged for every variable a script stores to.
+pub const VARIABLE_BYTES: usize = size_of::<(VariableId, VariableValue)>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryError {
+    pub requested: usize,
+    pub used: usize,
+    pub limit: usize
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "out of memory: {} more bytes requested with {} of the {} byte limit in use", self.requested, self.used, self.limit)
+    }
+}
+
+impl From<MemoryError> for String {
+    fn from(error: MemoryError) -> Self {
+        return error.to_string();
+    }
+}
+
+/// Counts the bytes held for a script (its variables and the string pool)
+/// against an optional limit. The stack has its own limit.
+pub struct MemoryAccount {
+    used: usize,
+    limit: Option<usize>
+}
+
+impl MemoryAccount {
+    pub fn new() -> Self {
+        return Self { used: 0, limit: None };
+    }
+
+    /// Records an allocation, or fails without recording it if it would go
+    /// over the limit.
+    pub fn charge(&mut self, bytes: usize) -> Result<(), MemoryError> {
+        let used = self.used.saturating_add(bytes);
+        if let Some(limit) = self.limit && used > limit {
+            return Err(MemoryError { requested: bytes, used: self.used, limit });
+        }
+        self.used = used;
+        return Ok(());
+    }
+
+    pub fn used(&self) -> usize {
+        return self.used;
+    }
+
+    /// Sets the limit for future charges; bytes already in use stay charged.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+}
@@ -17,19 +17,67 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
 use std::collections::BTreeMap;
-use crate::vm::handle_op;
-use crate::vm::handle_op::OpcodeHandler;
+
+use crate::compiler::VariableId;
 
 pub type Argument = i32;
 
+/// The most operands any opcode takes.
+pub const MAX_OPERANDS: usize = 2;
+
 #[allow(dead_code)]
 pub struct OpCode<'a> {
     pub symbol: &'a str,
     pub byte: u8,
-    pub handler: OpcodeHandler,
+    /// Builds the decoded form from the operands, of which the first `num_params` are used.
+    pub decode: fn([Argument; MAX_OPERANDS]) -> Op,
     pub num_params: usize
 }
 
+/// A decoded instruction with its operands inline, so that decoding never
+/// allocates and the VM can dispatch with a single match. Branch targets are
+/// byte offsets as read from a chunk, and instruction indices in a `DecodedChunk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Nop,
+    Const(Argument),
+    Pop,
+    Drop(Argument),
+    Copy(Argument),
+    Store(VariableId, Argument),
+    Swap,
+    Rot(Argument),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    AddC(Argument),
+    BNot,
+    BOr,
+    BAnd,
+    BXor,
+    Not,
+    Or,
+    And,
+    Xor,
+    Shl,
+    Shrl,
+    Shra,
+    Jump(Argument),
+    Ifz(Argument),
+    Ifnz(Argument),
+    Call(Argument),
+    Return(Argument),
+    Load(VariableId),
+    Write(Argument),
+    WriteS(Argument),
+    IncVar(VariableId, Argument),
+    CopyIfz(Argument),
+    CopyIfnz(Argument)
+}
+
 pub struct OpCodeLookup<'a> {
     symbol_lookup: BTreeMap<&'a str, &'a OpCode<'a>>,
     byte_lookup: Vec<Option<&'a OpCode<'a>>>
@@ -44,10 +92,9 @@ impl<'a> OpCodeLookup<'a> {
         byte_lookup.resize(u8::MAX as usize - u8::MIN as usize + 1, None);
 
         // Add opcodes to tables
-        for i in 0..OPCODES.len() {
-            let opcode = &OPCODES[i];
-            match &byte_lookup[opcode.byte as usize] {
-                &Some(opcode2) => {
+        for opcode in OPCODES {
+            match byte_lookup[opcode.byte as usize] {
+                Some(opcode2) => {
                     panic!(
                         "Opcodes {} and {} both have byte 0x{:02x}",
                         opcode.symbol,
@@ -55,7 +102,7 @@ impl<'a> OpCodeLookup<'a> {
                         opcode.byte
                     );
                 },
-                &None => {
+                None => {
                     byte_lookup[opcode.byte as usize] = Some(opcode);
                 }
             }
@@ -77,149 +124,170 @@ impl<'a> OpCodeLookup<'a> {
         return Self {symbol_lookup, byte_lookup};
     }
 
-    pub fn from_symbol(&'a self, symbol: &'a str) -> Option<&'a OpCode<'a>> {
+    pub fn from_symbol(&self, symbol: &str) -> Option<&'a OpCode<'a>> {
         match self.symbol_lookup.get(&symbol.to_uppercase()[..]) {
             Some(&opcode) => Some(opcode),
             None => None
         }
     }
 
-    pub fn from_byte(&'a self, byte: u8) -> Option<&'a OpCode<'a>> {
+    pub fn from_byte(&self, byte: u8) -> Option<&'a OpCode<'a>> {
         return self.byte_lookup[byte as usize];
     }
+
+    /// Every opcode symbol, in alphabetical order.
+    pub fn symbols(&self) -> impl Iterator<Item = &'a str> + '_ {
+        return self.symbol_lookup.keys().copied();
+    }
 }
 
 const OPCODES: &[OpCode] = &[
     OpCode {
         symbol: "NOP", byte: 0x00,
-        handler: handle_op::handle_nop, num_params: 0
+        decode: |_| Op::Nop, num_params: 0
     },
     OpCode {
         symbol: "CONST", byte: 0x01,
-        handler: handle_op::handle_constant, num_params: 1
+        decode: |args| Op::Const(args[0]), num_params: 1
     },
     OpCode {
         symbol: "POP", byte: 0x02,
-        handler: handle_op::handle_pop, num_params: 0
+        decode: |_| Op::Pop, num_params: 0
     },
     OpCode {
         symbol: "DROP", byte: 0x03,
-        handler: handle_op::handle_drop, num_params: 1
+        decode: |args| Op::Drop(args[0]), num_params: 1
     },
     OpCode {
         symbol: "COPY", byte: 0x04,
-        handler: handle_op::handle_copy, num_params: 1
+        decode: |args| Op::Copy(args[0]), num_params: 1
     },
     OpCode {
         symbol: "STORE", byte: 0x05,
-        handler: handle_op::handle_store, num_params: 2
+        decode: |args| Op::Store(args[0], args[1]), num_params: 2
     },
     OpCode {
         symbol: "SWAP", byte: 0x06,
-        handler: handle_op::handle_swap, num_params: 0
+        decode: |_| Op::Swap, num_params: 0
     },
     OpCode {
         symbol: "ROT", byte: 0x07,
-        handler: handle_op::handle_rot, num_params: 1
+        decode: |args| Op::Rot(args[0]), num_params: 1
     },
     OpCode {
         symbol: "NEG", byte: 0x10,
-        handler: handle_op::handle_neg, num_params: 0
+        decode: |_| Op::Neg, num_params: 0
     },
     OpCode {
         symbol: "ADD", byte: 0x11,
-        handler: handle_op::handle_add, num_params: 0
+        decode: |_| Op::Add, num_params: 0
     },
     OpCode {
         symbol: "SUB", byte: 0x12,
-        handler: handle_op::handle_sub, num_params: 0
+        decode: |_| Op::Sub, num_params: 0
     },
     OpCode {
         symbol: "MUL", byte: 0x13,
-        handler: handle_op::handle_mul, num_params: 0
+        decode: |_| Op::Mul, num_params: 0
     },
     OpCode {
         symbol: "DIV", byte: 0x14,
-        handler: handle_op::handle_div, num_params: 0
+        decode: |_| Op::Div, num_params: 0
     },
     OpCode {
         symbol: "MOD", byte: 0x15,
-        handler: handle_op::handle_mod, num_params: 0
+        decode: |_| Op::Mod, num_params: 0
+    },
+    OpCode {
+        symbol: "ADDC", byte: 0x16,
+        decode: |args| Op::AddC(args[0]), num_params: 1
     },
     OpCode {
         symbol: "BNOT", byte: 0x20,
-        handler: handle_op::handle_bnot, num_params: 0
+        decode: |_| Op::BNot, num_params: 0
     },
     OpCode {
         symbol: "BOR", byte: 0x21,
-        handler: handle_op::handle_bor, num_params: 0
+        decode: |_| Op::BOr, num_params: 0
     },
     OpCode {
         symbol: "BAND", byte: 0x22,
-        handler: handle_op::handle_band, num_params: 0
+        decode: |_| Op::BAnd, num_params: 0
     },
     OpCode {
         symbol: "BXOR", byte: 0x23,
-        handler: handle_op::handle_bxor, num_params: 0
+        decode: |_| Op::BXor, num_params: 0
     },
     OpCode {
         symbol: "NOT", byte: 0x24,
-        handler: handle_op::handle_not, num_params: 0
+        decode: |_| Op::Not, num_params: 0
     },
     OpCode {
         symbol: "OR", byte: 0x25,
-        handler: handle_op::handle_or, num_params: 0
+        decode: |_| Op::Or, num_params: 0
     },
     OpCode {
         symbol: "AND", byte: 0x26,
-        handler: handle_op::handle_and, num_params: 0
+        decode: |_| Op::And, num_params: 0
     },
     OpCode {
         symbol: "XOR", byte: 0x27,
-        handler: handle_op::handle_xor, num_params: 0
+        decode: |_| Op::Xor, num_params: 0
     },
     OpCode {
         symbol: "SHL", byte: 0x28,
-        handler: handle_op::handle_shl, num_params: 0
+        decode: |_| Op::Shl, num_params: 0
     },
     OpCode {
         symbol: "SHRL", byte: 0x29,
-        handler: handle_op::handle_shrl, num_params: 0
+        decode: |_| Op::Shrl, num_params: 0
     },
     OpCode {
         symbol: "SHRA", byte: 0x2a,
-        handler: handle_op::handle_shra, num_params: 0
+        decode: |_| Op::Shra, num_params: 0
     },
     OpCode {
         symbol: "JUMP", byte: 0x30,
-        handler: handle_op::handle_jump, num_params: 1
+        decode: |args| Op::Jump(args[0]), num_params: 1
     },
     OpCode {
         symbol: "IFZ", byte: 0x31,
-        handler: handle_op::handle_ifz, num_params: 1
+        decode: |args| Op::Ifz(args[0]), num_params: 1
     },
     OpCode {
         symbol: "IFNZ", byte: 0x32,
-        handler: handle_op::handle_ifnz, num_params: 1
+        decode: |args| Op::Ifnz(args[0]), num_params: 1
     },
     OpCode {
         symbol: "CALL", byte: 0x33,
-        handler: handle_op::handle_call, num_params: 1
+        decode: |args| Op::Call(args[0]), num_params: 1
     },
     OpCode {
         symbol: "RETURN", byte: 0x34,
-        handler: handle_op::handle_return, num_params: 1
+        decode: |args| Op::Return(args[0]), num_params: 1
     },
     OpCode {
         symbol: "LOAD", byte: 0x35,
-        handler: handle_op::handle_load, num_params: 1
+        decode: |args| Op::Load(args[0]), num_params: 1
     },
     OpCode {
         symbol: "WRITE", byte: 0x36,
-        handler: handle_op::handle_write, num_params: 1
+        decode: |args| Op::Write(args[0]), num_params: 1
     },
     OpCode {
         symbol: "WRITES", byte: 0x37,
-        handler: handle_op::handle_writes, num_params: 1
+        decode: |args| Op::WriteS(args[0]), num_params: 1
+    },
+    OpCode {
+        symbol: "INCVAR", byte: 0x38,
+        decode: |args| Op::IncVar(args[0], args[1]), num_params: 2
+    },
+    OpCode {
+        symbol: "COPYIFZ", byte: 0x39,
+        decode: |args| Op::CopyIfz(args[0]), num_params: 1
+    },
+    OpCode {
+        symbol: "COPYIFNZ", byte: 0x3a,
+        decode: |args| Op::CopyIfnz(args[0]), num_params: 1
     },
 ];
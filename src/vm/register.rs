@@ -0,0 +1,204 @@
+/*
+vm/register.rs: A register machine, as an alternative to the stack VM
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{cell::RefCell, fmt};
+
+use rustc_hash::FxHashMap;
+
+use crate::compiler::{CCILTypeId, VariableId};
+use crate::constants::{fileno_const, type_id_const};
+use crate::vm::{arithmetic::ArithmeticMode, opcode::Argument, read_string, stack::StackItem, variable_value::VariableValue};
+
+pub type Register = u32;
+
+/// A register machine instruction, with the destination register first.
+/// Every operand names a register except the constants of `Const` and
+/// `AddImm` and the file numbers of writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterOp {
+    Const(Register, Argument),
+    Move(Register, Register),
+    Add(Register, Register, Register),
+    AddImm(Register, Register, Argument),
+    Sub(Register, Register, Register),
+    Mul(Register, Register, Register),
+    Div(Register, Register, Register),
+    Mod(Register, Register, Register),
+    Write(Register, Argument),
+    WriteS(Register, Argument)
+}
+
+impl RegisterOp {
+    /// The same instruction with every register operand passed through `map`.
+    pub fn map_registers(self, map: impl Fn(Register) -> Register) -> Self {
+        use RegisterOp::*;
+        return match self {
+            Const(dst, constant) => Const(map(dst), constant),
+            Move(dst, src) => Move(map(dst), map(src)),
+            Add(dst, a, b) => Add(map(dst), map(a), map(b)),
+            AddImm(dst, a, constant) => AddImm(map(dst), map(a), constant),
+            Sub(dst, a, b) => Sub(map(dst), map(a), map(b)),
+            Mul(dst, a, b) => Mul(map(dst), map(a), map(b)),
+            Div(dst, a, b) => Div(map(dst), map(a), map(b)),
+            Mod(dst, a, b) => Mod(map(dst), map(a), map(b)),
+            Write(src, fileno) => Write(map(src), fileno),
+            WriteS(src, fileno) => WriteS(map(src), fileno)
+        };
+    }
+}
+
+impl fmt::Display for RegisterOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RegisterOp::*;
+        match self {
+            Const(dst, constant) => write!(f, "CONST r{} {}", dst, constant),
+            Move(dst, src) => write!(f, "MOVE r{} r{}", dst, src),
+            Add(dst, a, b) => write!(f, "ADD r{} r{} r{}", dst, a, b),
+            AddImm(dst, a, constant) => write!(f, "ADDI r{} r{} {}", dst, a, constant),
+            Sub(dst, a, b) => write!(f, "SUB r{} r{} r{}", dst, a, b),
+            Mul(dst, a, b) => write!(f, "MUL r{} r{} r{}", dst, a, b),
+            Div(dst, a, b) => write!(f, "DIV r{} r{} r{}", dst, a, b),
+            Mod(dst, a, b) => write!(f, "MOD r{} r{} r{}", dst, a, b),
+            Write(src, fileno) => write!(f, "WRITE r{} {}", src, fileno),
+            WriteS(src, fileno) => write!(f, "WRITES r{} {}", src, fileno)
+        }
+    }
+}
+
+/// Code for the register machine. Variable `id` lives in register `id`, and
+/// temporaries in the registers after the last variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterProgram {
+    pub ops: Vec<RegisterOp>,
+    /// How many registers the code uses.
+    pub registers: usize,
+    /// The type of every variable once the program has run, by id.
+    pub variable_types: Vec<CCILTypeId>
+}
+
+/// Runs register programs. Registers, and so variables, persist between
+/// programs, as the stack VM's variables do.
+pub struct RegisterMachine<'b> {
+    registers: Vec<StackItem>,
+    variable_types: Vec<CCILTypeId>,
+    string_pool: &'b RefCell<Vec<u8>>,
+    arithmetic: ArithmeticMode
+}
+
+impl<'b> RegisterMachine<'b> {
+    pub fn new(string_pool: &'b RefCell<Vec<u8>>) -> Self {
+        Self {
+            registers: Vec::new(),
+            variable_types: Vec::new(),
+            string_pool,
+            arithmetic: ArithmeticMode::default()
+        }
+    }
+
+    /// Sets what integer arithmetic does on overflow, as for the stack VM.
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) {
+        self.arithmetic = mode;
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        return self.arithmetic;
+    }
+
+    pub fn execute(&mut self, program: &RegisterProgram) {
+        if let Err(err) = self.try_execute(program) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Executes a program like `execute`, but returns the first runtime error
+    /// instead of panicking.
+    pub fn try_execute(&mut self, program: &RegisterProgram) -> Result<(), String> {
+        if self.registers.len() < program.registers {
+            self.registers.resize(program.registers, 0);
+        }
+        self.variable_types.clone_from(&program.variable_types);
+        let debug = crate::debug_enabled();
+
+        for (index, op) in program.ops.iter().enumerate() {
+            if debug {
+                print!("{} {}", index, op);
+            }
+            if let Err(err) = self.step(*op) {
+                return Err(format!("Error at instruction {}: {}", index, err));
+            }
+            if debug {
+                println!("\t{:?}", self.registers);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every register, variables first.
+    pub fn registers(&self) -> &[StackItem] {
+        return &self.registers;
+    }
+
+    /// Every variable that has been assigned, like the stack VM's variables.
+    pub fn variables(&self) -> FxHashMap<VariableId, VariableValue> {
+        let mut variables = FxHashMap::<VariableId, VariableValue>::default();
+        for (id, type_id) in self.variable_types.iter().enumerate() {
+            let data = self.registers[id];
+            let value = match *type_id {
+                type_id_const::STRING => VariableValue::StringLiteral(data as usize),
+                type_id_const::NUMBER => VariableValue::Number(data),
+                type_id_const::NULL => VariableValue::Null,
+                type_id_const::BOOLEAN => VariableValue::Boolean(data != 0),
+                _ => continue
+            };
+            variables.insert(id as VariableId, value);
+        }
+        return variables;
+    }
+
+    #[inline]
+    fn step(&mut self, op: RegisterOp) -> Result<(), String> {
+        use RegisterOp::*;
+        let registers = &mut self.registers;
+        let arithmetic = self.arithmetic;
+        match op {
+            Const(dst, constant) => registers[dst as usize] = constant as StackItem,
+            Move(dst, src) => registers[dst as usize] = registers[src as usize],
+            Add(dst, a, b) => registers[dst as usize] = arithmetic.add(registers[a as usize], registers[b as usize])?,
+            AddImm(dst, a, constant) => registers[dst as usize] = arithmetic.add(registers[a as usize], constant as StackItem)?,
+            Sub(dst, a, b) => registers[dst as usize] = arithmetic.sub(registers[a as usize], registers[b as usize])?,
+            Mul(dst, a, b) => registers[dst as usize] = arithmetic.mul(registers[a as usize], registers[b as usize])?,
+            Div(dst, a, b) => registers[dst as usize] = arithmetic.div(registers[a as usize], registers[b as usize])?,
+            Mod(dst, a, b) => registers[dst as usize] = arithmetic.rem(registers[a as usize], registers[b as usize])?,
+            Write(src, fileno) => write_to(fileno, &registers[src as usize].to_string())?,
+            WriteS(src, fileno) => {
+                let text = read_string(&self.string_pool.borrow(), registers[src as usize] as usize)?;
+                write_to(fileno, &text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_to(fileno: Argument, text: &str) -> Result<(), String> {
+    match fileno {
+        fileno_const::STDOUT => println!("{}", text),
+        fileno_const::STDERR => eprintln!("{}", text),
+        other_value => return Err(format!("Cannot write to file {}", other_value))
+    }
+    Ok(())
+}
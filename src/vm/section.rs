@@ -0,0 +1,210 @@
+/*
+vm/section.rs: Defines the sectioned bytecode layout
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::compiler::VariableId;
+use crate::constants::header_flag_const;
+use crate::vm::chunk::{Chunk, ChunkError, ChunkOffset, OperandEncoding};
+use crate::vm::opcode::Argument;
+
+// section ids for sectioned chunks
+pub mod section_id_const {
+    pub const CODE: u8 = 0x01;
+    pub const STRINGS: u8 = 0x02;
+    pub const EXPORTS: u8 = 0x03;
+    pub const RELOCATIONS: u8 = 0x04;
+    pub const SYMBOLS: u8 = 0x05;
+}
+
+// symbol kind ids, as stored in the symbol section
+pub mod symbol_id_const {
+    pub const FUNCTION: u8 = 0x00;
+    pub const VARIABLE: u8 = 0x01;
+}
+
+/// A tagged blob in a sectioned chunk. On disk each section is its id byte,
+/// its length as a fixed-width argument, then its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub id: u8,
+    pub data: Vec<u8>
+}
+
+/// Names for the functions and global variables of an executable program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    pub functions: Vec<(String, ChunkOffset)>,
+    pub variables: Vec<(String, VariableId)>
+}
+
+/// An executable program split into its parts, whatever layout it was stored in.
+pub struct Executable {
+    pub code: Vec<u8>,
+    pub string_pool: Vec<u8>,
+    pub symbols: SymbolTable,
+    pub encoding: OperandEncoding
+}
+
+impl SymbolTable {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.variables.is_empty()
+    }
+
+    /// Finds the code offset of the named function.
+    pub fn function(&self, name: &str) -> Option<ChunkOffset> {
+        self.functions.iter().find(|(function, _)| function == name).map(|(_, offset)| *offset)
+    }
+
+    /// Finds the id of the named global variable.
+    pub fn variable(&self, name: &str) -> Option<VariableId> {
+        self.variables.iter().find(|(variable, _)| variable == name).map(|(_, id)| *id)
+    }
+
+    /// Each entry is a kind byte, a name, then an offset or variable id.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::<u8>::new();
+        for (name, offset) in &self.functions {
+            data.write_byte(symbol_id_const::FUNCTION);
+            write_name(&mut data, name);
+            data.write_arg(*offset as Argument);
+        }
+        for (name, id) in &self.variables {
+            data.write_byte(symbol_id_const::VARIABLE);
+            write_name(&mut data, name);
+            data.write_arg(*id);
+        }
+        return data;
+    }
+
+    pub fn decode(data: &Vec<u8>) -> Result<Self, ChunkError> {
+        let mut symbols = SymbolTable::default();
+        let mut offset = 0;
+        while offset < data.len() {
+            let kind = data[offset];
+            let (name, next) = read_name(data, section_id_const::SYMBOLS, offset + 1)?;
+            let value = data.read_arg(next)?;
+            match kind {
+                symbol_id_const::FUNCTION => match ChunkOffset::try_from(value) {
+                    Ok(function_offset) => symbols.functions.push((name, function_offset)),
+                    Err(_) => return Err(ChunkError::MalformedSection { section: section_id_const::SYMBOLS, offset })
+                },
+                symbol_id_const::VARIABLE => symbols.variables.push((name, value)),
+                _ => return Err(ChunkError::MalformedSection { section: section_id_const::SYMBOLS, offset })
+            }
+            offset = next + 4;
+        }
+        return Ok(symbols);
+    }
+}
+
+impl Executable {
+    /// Serializes to a headered, sectioned chunk. The symbol section is only
+    /// written when there are symbols to record.
+    pub fn to_chunk(&self, flags: u8, timestamp: i64) -> Vec<u8> {
+        let mut sections = vec![
+            Section { id: section_id_const::CODE, data: self.code.clone() },
+            Section { id: section_id_const::STRINGS, data: self.string_pool.clone() }
+        ];
+        if !self.symbols.is_empty() {
+            sections.push(Section { id: section_id_const::SYMBOLS, data: self.symbols.encode() });
+        }
+        let mut chunk = encode_sections(&sections);
+        return chunk.with_header(flags | self.encoding.header_flag() | header_flag_const::SECTIONED, timestamp);
+    }
+}
+
+pub fn encode_sections(sections: &[Section]) -> Vec<u8> {
+    let mut body = Vec::<u8>::new();
+    for section in sections {
+        body.write_byte(section.id);
+        body.write_arg(section.data.len() as Argument);
+        body.extend_from_slice(&section.data);
+    }
+    return body;
+}
+
+/// Splits a headerless sectioned body back into its sections.
+/// Offsets in errors are relative to the start of the body.
+pub fn decode_sections(body: &[u8]) -> Result<Vec<Section>, ChunkError> {
+    let body = body.to_vec();
+    let mut sections = Vec::<Section>::new();
+    let mut offset = 0;
+    while offset < body.len() {
+        let id = body[offset];
+        let Ok(len) = usize::try_from(body.read_arg(offset + 1)?) else {
+            return Err(ChunkError::MalformedSection { section: id, offset });
+        };
+        let start = offset + 5;
+        let end = match start.checked_add(len) {
+            Some(end) if end <= body.len() => end,
+            _ => return Err(ChunkError::UnexpectedEnd { offset: start, needed: len, len: body.len() })
+        };
+        sections.push(Section { id, data: body[start..end].to_vec() });
+        offset = end;
+    }
+    return Ok(sections);
+}
+
+/// Finds the data of the first section with the given id.
+pub fn find_section(sections: &[Section], id: u8) -> Option<&Vec<u8>> {
+    sections.iter().find(|section| section.id == id).map(|section| &section.data)
+}
+
+/// Appends a length-prefixed name, as used by symbol tables.
+pub fn write_name(data: &mut Vec<u8>, name: &str) {
+    data.write_arg(name.len() as Argument);
+    data.extend_from_slice(name.as_bytes());
+}
+
+/// Reads a length-prefixed name from the data of `section`, returning it and
+/// the offset just past it.
+pub fn read_name(data: &Vec<u8>, section: u8, offset: ChunkOffset) -> Result<(String, ChunkOffset), ChunkError> {
+    let Ok(len) = usize::try_from(data.read_arg(offset)?) else {
+        return Err(ChunkError::MalformedSection { section, offset });
+    };
+    let start = offset + 4;
+    let end = match start.checked_add(len) {
+        Some(end) if end <= data.len() => end,
+        _ => return Err(ChunkError::UnexpectedEnd { offset: start, needed: len, len: data.len() })
+    };
+    let name = String::from_utf8_lossy(&data[start..end]).into_owned();
+    return Ok((name, end));
+}
+
+/// Loads a program from file contents. Accepts bare code, a header followed by
+/// code, and sectioned files; relocatable objects must be linked first.
+pub fn load_executable(file: &Vec<u8>) -> Result<Executable, ChunkError> {
+    let flags = file.header_flags();
+    let encoding = file.operand_encoding();
+    let body = file.without_header()?;
+
+    if flags & header_flag_const::RELOCATABLE != 0 {
+        return Err(ChunkError::NotExecutable);
+    }
+    if flags & header_flag_const::SECTIONED == 0 {
+        return Ok(Executable { code: body, string_pool: Vec::new(), symbols: SymbolTable::default(), encoding });
+    }
+
+    let sections = decode_sections(&body)?;
+    let code = find_section(&sections, section_id_const::CODE).cloned().unwrap_or_default();
+    let string_pool = find_section(&sections, section_id_const::STRINGS).cloned().unwrap_or_default();
+    let symbols = match find_section(&sections, section_id_const::SYMBOLS) {
+        Some(data) => SymbolTable::decode(data)?,
+        None => SymbolTable::default()
+    };
+    return Ok(Executable { code, string_pool, symbols, encoding });
+}
@@ -21,69 +21,142 @@ use std::fmt;
 pub type StackPointer = i32;
 pub type StackItem = i32;
 
-pub trait Shift<T> {
-    fn logical_shift(self, shift_amount: T) -> Self;
-    fn arithmetic_shift(self, shift_amount: T) -> Self;
+/// Items a stack holds at most, unless configured otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// Pushing would take the stack past its maximum depth.
+    Overflow,
+    /// The offset from the top is negative or past the bottom of the stack.
+    Underflow(StackPointer)
 }
 
-impl Shift<StackItem> for i32 {
-    fn logical_shift(self, shift_amount: StackItem) -> Self {
-        assert!(shift_amount >= 0);
-
-        if shift_amount == 0 {
-            return self;
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackError::Overflow => write!(f, "stack overflow"),
+            StackError::Underflow(offset) => write!(f, "stack underflow at offset {}", offset)
         }
-
-        // We know shift_amount >= 1, so MSB will be 0 after shift, so
-        // u8->i8 conversion is safe.
-        return ((self as i32) >> shift_amount).try_into().unwrap();
     }
+}
 
-    fn arithmetic_shift(self, shift_amount: StackItem) -> Self {
-        return (self as i32) >> (shift_amount as usize) as Self;
+impl From<StackError> for String {
+    fn from(error: StackError) -> Self {
+        return error.to_string();
     }
 }
 
+/// A stack of items addressed by their offset from the top, which is 0.
 pub trait Stack {
     fn new() -> Self;
-    fn get(&self, offset: StackPointer) -> StackItem;
-    fn insert(&mut self, offset: StackPointer, item: StackItem);
-    fn set(&mut self, offset: StackPointer, item: StackItem);
-    fn push(&mut self, item: StackItem);
-    fn pop(&mut self) -> Option<StackItem>;
+    fn get(&self, offset: StackPointer) -> Result<StackItem, StackError>;
+    fn insert(&mut self, offset: StackPointer, item: StackItem) -> Result<(), StackError>;
+    fn set(&mut self, offset: StackPointer, item: StackItem) -> Result<(), StackError>;
+    fn push(&mut self, item: StackItem) -> Result<(), StackError>;
+    fn pop(&mut self) -> Result<StackItem, StackError>;
+    fn peek(&self) -> Result<StackItem, StackError>;
+    /// Removes items from the top until at most `len` are left.
+    fn truncate(&mut self, len: usize);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn max_depth(&self) -> usize;
+    fn set_max_depth(&mut self, max_depth: usize);
+    /// The most items the stack has held at once.
+    fn peak_depth(&self) -> usize;
 }
 
 pub struct VecStack {
-    items: Vec<StackItem>
+    items: Vec<StackItem>,
+    max_depth: usize,
+    peak_depth: usize
+}
+
+impl VecStack {
+    /// The index into `items` of an offset from the top.
+    #[inline]
+    fn index(&self, offset: StackPointer) -> Result<usize, StackError> {
+        if offset < 0 || offset as usize >= self.items.len() {
+            return Err(StackError::Underflow(offset));
+        }
+        return Ok(self.items.len() - 1 - offset as usize);
+    }
+
+    #[inline]
+    fn grow(&mut self) -> Result<(), StackError> {
+        if self.items.len() >= self.max_depth {
+            return Err(StackError::Overflow);
+        }
+        self.peak_depth = self.peak_depth.max(self.items.len() + 1);
+        return Ok(());
+    }
 }
 
 impl Stack for VecStack {
     fn new() -> Self {
         let items = Vec::<StackItem>::new();
-        return Self { items };
+        return Self { items, max_depth: DEFAULT_MAX_DEPTH, peak_depth: 0 };
     }
 
-    fn get(&self, offset: StackPointer) -> StackItem {
-        let index = self.items.len() - 1 - offset as usize;
-        return self.items[index];
+    fn get(&self, offset: StackPointer) -> Result<StackItem, StackError> {
+        return Ok(self.items[self.index(offset)?]);
     }
 
-    fn insert(&mut self, offset: StackPointer, item: StackItem) {
+    fn insert(&mut self, offset: StackPointer, item: StackItem) -> Result<(), StackError> {
+        // inserting at offset len() puts the item at the bottom
+        if offset < 0 || offset as usize > self.items.len() {
+            return Err(StackError::Underflow(offset));
+        }
+        self.grow()?;
         let index = self.items.len() - offset as usize;
         self.items.insert(index, item);
+        return Ok(());
     }
 
-    fn set(&mut self, offset: StackPointer, item: StackItem) {
-        let index = self.items.len() - 1 - offset as usize;
+    fn set(&mut self, offset: StackPointer, item: StackItem) -> Result<(), StackError> {
+        let index = self.index(offset)?;
         self.items[index] = item;
+        return Ok(());
     }
 
-    fn push(&mut self, item: StackItem) {
+    #[inline]
+    fn push(&mut self, item: StackItem) -> Result<(), StackError> {
+        self.grow()?;
         self.items.push(item);
+        return Ok(());
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<StackItem, StackError> {
+        return self.items.pop().ok_or(StackError::Underflow(0));
+    }
+
+    fn peek(&self) -> Result<StackItem, StackError> {
+        return self.items.last().copied().ok_or(StackError::Underflow(0));
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.items.truncate(len);
+    }
+
+    fn len(&self) -> usize {
+        return self.items.len();
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.items.is_empty();
+    }
+
+    fn max_depth(&self) -> usize {
+        return self.max_depth;
+    }
+
+    fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
     }
 
-    fn pop(&mut self) -> Option<StackItem> {
-        return self.items.pop();
+    fn peak_depth(&self) -> usize {
+        return self.peak_depth;
     }
 }
 
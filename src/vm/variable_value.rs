@@ -1,5 +1,6 @@
 use ordered_float::OrderedFloat;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum VariableValue {
     // Value is location in string pool
     StringLiteral(usize),
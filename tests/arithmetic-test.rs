@@ -0,0 +1,216 @@
+/*
+arithmetic-test.rs: Tests for integer overflow and division by zero
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use ccil::compiler::Compiler;
+    use ccil::parser::{Parser, token::Token};
+    use ccil::vm::VirtualMachine;
+    use ccil::vm::arithmetic::{ArithmeticError, ArithmeticMode};
+    use ccil::vm::chunk::Chunk;
+    use ccil::vm::opcode::OpCodeLookup;
+    use ccil::vm::register::RegisterMachine;
+    use ccil::vm::stack::Stack;
+    use ccil::vm::variable_value::VariableValue;
+
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+
+    /// Runs the ops and returns the top of the stack, or the error without
+    /// its offset.
+    fn run(mode: ArithmeticMode, ops: &[(&str, &[i32])]) -> Result<i32, String> {
+        let lookup = OpCodeLookup::new();
+        let mut chunk = Vec::<u8>::new();
+        for (symbol, args) in ops {
+            chunk.write_op(lookup.from_symbol(symbol).unwrap());
+            for arg in *args {
+                chunk.write_arg(*arg);
+            }
+        }
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_arithmetic_mode(mode);
+        if let Err(err) = vm.try_execute(&chunk) {
+            return Err(err.split_once(": ").unwrap().1.to_string());
+        }
+        // INCVAR leaves nothing on the stack, so its tests store to variable 0
+        if let Some(VariableValue::Number(value)) = vm.variables().get(&0) {
+            return Ok(*value);
+        }
+        return Ok(vm.stack().peek().unwrap());
+    }
+
+    fn binary(mode: ArithmeticMode, symbol: &str, a: i32, b: i32) -> Result<i32, String> {
+        return run(mode, &[("CONST", &[a]), ("CONST", &[b]), (symbol, &[])]);
+    }
+
+    fn unary(mode: ArithmeticMode, symbol: &str, a: i32) -> Result<i32, String> {
+        return run(mode, &[("CONST", &[a]), (symbol, &[])]);
+    }
+
+    fn overflow() -> Result<i32, String> {
+        return Err(ArithmeticError::Overflow.to_string());
+    }
+
+    #[test]
+    fn add_and_sub_overflow_at_the_boundaries() {
+        use ArithmeticMode::*;
+        assert_eq!(binary(Checked, "ADD", MAX - 1, 1), Ok(MAX));
+        assert_eq!(binary(Checked, "ADD", MAX, 1), overflow());
+        assert_eq!(binary(Checked, "ADD", MIN, -1), overflow());
+        assert_eq!(binary(Wrapping, "ADD", MAX, 1), Ok(MIN));
+        assert_eq!(binary(Wrapping, "ADD", MIN, -1), Ok(MAX));
+
+        assert_eq!(binary(Checked, "SUB", MIN + 1, 1), Ok(MIN));
+        assert_eq!(binary(Checked, "SUB", MIN, 1), overflow());
+        assert_eq!(binary(Checked, "SUB", 0, MIN), overflow());
+        assert_eq!(binary(Checked, "SUB", -1, MIN), Ok(MAX));
+        assert_eq!(binary(Wrapping, "SUB", MIN, 1), Ok(MAX));
+        assert_eq!(binary(Wrapping, "SUB", 0, MIN), Ok(MIN));
+    }
+
+    #[test]
+    fn mul_overflows_at_the_boundaries() {
+        use ArithmeticMode::*;
+        assert_eq!(binary(Checked, "MUL", 46341, 46340), Ok(2147441940));
+        assert_eq!(binary(Checked, "MUL", 46341, 46341), overflow());
+        assert_eq!(binary(Checked, "MUL", MIN, -1), overflow());
+        assert_eq!(binary(Checked, "MUL", MIN, 1), Ok(MIN));
+        assert_eq!(binary(Wrapping, "MUL", 46341, 46341), Ok(-2147479015));
+        assert_eq!(binary(Wrapping, "MUL", MIN, -1), Ok(MIN));
+        assert_eq!(binary(Wrapping, "MUL", MAX, 2), Ok(-2));
+    }
+
+    #[test]
+    fn div_and_mod_by_zero_fail_in_every_mode() {
+        for mode in [ArithmeticMode::Checked, ArithmeticMode::Wrapping] {
+            for symbol in ["DIV", "MOD"] {
+                for a in [0, 1, MIN, MAX] {
+                    assert_eq!(binary(mode, symbol, a, 0), Err("division by zero".to_string()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn div_and_mod_round_towards_zero() {
+        use ArithmeticMode::*;
+        assert_eq!(binary(Checked, "DIV", -7, 2), Ok(-3));
+        assert_eq!(binary(Checked, "MOD", -7, 2), Ok(-1));
+        assert_eq!(binary(Checked, "MOD", 7, -2), Ok(1));
+        assert_eq!(binary(Checked, "DIV", MIN, 1), Ok(MIN));
+        assert_eq!(binary(Checked, "DIV", MAX, -1), Ok(-MAX));
+        assert_eq!(binary(Checked, "MOD", MIN, MAX), Ok(-1));
+
+        // the quotient MAX + 1 does not fit
+        assert_eq!(binary(Checked, "DIV", MIN, -1), overflow());
+        assert_eq!(binary(Checked, "MOD", MIN, -1), overflow());
+        assert_eq!(binary(Wrapping, "DIV", MIN, -1), Ok(MIN));
+        assert_eq!(binary(Wrapping, "MOD", MIN, -1), Ok(0));
+    }
+
+    #[test]
+    fn neg_overflows_only_on_min() {
+        use ArithmeticMode::*;
+        assert_eq!(unary(Checked, "NEG", MAX), Ok(-MAX));
+        assert_eq!(unary(Checked, "NEG", -MAX), Ok(MAX));
+        assert_eq!(unary(Checked, "NEG", MIN), overflow());
+        assert_eq!(unary(Wrapping, "NEG", MIN), Ok(MIN));
+    }
+
+    #[test]
+    fn superinstructions_overflow_like_add() {
+        use ArithmeticMode::*;
+        let addc = |mode, a, b| run(mode, &[("CONST", &[a]), ("ADDC", &[b])]);
+        assert_eq!(addc(Checked, MAX - 1, 1), Ok(MAX));
+        assert_eq!(addc(Checked, MAX, 1), overflow());
+        assert_eq!(addc(Checked, MIN, -1), overflow());
+        assert_eq!(addc(Wrapping, MAX, 1), Ok(MIN));
+
+        let incvar = |mode, a, b| run(mode, &[("CONST", &[a]), ("STORE", &[0, 1]), ("INCVAR", &[0, b])]);
+        assert_eq!(incvar(Checked, MIN + 1, -1), Ok(MIN));
+        assert_eq!(incvar(Checked, MAX, 1), overflow());
+        assert_eq!(incvar(Checked, MIN, -1), overflow());
+        assert_eq!(incvar(Wrapping, MIN, -1), Ok(MAX));
+    }
+
+    #[test]
+    fn shifts_check_their_amount() {
+        use ArithmeticMode::*;
+        assert_eq!(binary(Checked, "SHL", 1, 31), Ok(MIN));
+        assert_eq!(binary(Checked, "SHL", 3, 31), Ok(MIN));
+        assert_eq!(binary(Checked, "SHRL", MIN, 31), Ok(1));
+        assert_eq!(binary(Checked, "SHRA", MIN, 31), Ok(-1));
+        assert_eq!(binary(Checked, "SHRL", -1, 0), Ok(-1));
+
+        for symbol in ["SHL", "SHRL", "SHRA"] {
+            assert_eq!(binary(Checked, symbol, 1, 32), Err("invalid shift amount 32".to_string()));
+            assert_eq!(binary(Checked, symbol, 1, -1), Err("invalid shift amount -1".to_string()));
+        }
+
+        // wrapping takes the amount modulo 32
+        assert_eq!(binary(Wrapping, "SHL", 1, 33), Ok(2));
+        assert_eq!(binary(Wrapping, "SHRL", MIN, -1), Ok(1));
+        assert_eq!(binary(Wrapping, "SHRA", MIN, 32), Ok(MIN));
+    }
+
+    #[test]
+    fn the_default_mode_is_checked() {
+        let string_pool = RefCell::new(Vec::new());
+        assert_eq!(VirtualMachine::new(&string_pool).arithmetic_mode(), ArithmeticMode::Checked);
+        assert_eq!(RegisterMachine::new(&string_pool).arithmetic_mode(), ArithmeticMode::Checked);
+        assert_eq!(binary(ArithmeticMode::default(), "ADD", MAX, 1), overflow());
+    }
+
+    #[test]
+    fn engines_agree_on_overflow() {
+        let sources = [
+            "a = 2147483647; b = a + 1;",
+            "a = 0 - 2147483647; b = a - 2;",
+            "a = 65536; b = a * a;",
+            "a = 0 - 2147483647; b = a - 1; m = 0 - 1; c = b / m;",
+            "a = 0 - 2147483647; b = a - 1; m = 0 - 1; c = b % m;",
+            "a = 7; b = 0; c = a % b;",
+            "a = 7; b = 3; c = a / b;"
+        ];
+        for mode in [ArithmeticMode::Checked, ArithmeticMode::Wrapping] {
+            for source in sources {
+                let mut parser = Parser::new(Token::full_scan(source));
+                parser.full_parse();
+
+                let stack_compiler = Compiler::new();
+                let mut vm = VirtualMachine::new(&stack_compiler.string_pool);
+                vm.set_arithmetic_mode(mode);
+                let stack_result = vm.try_execute(&stack_compiler.compile(&parser.expressions))
+                    .map(|_| vm.variables().clone())
+                    .map_err(|err| err.split_once(": ").unwrap().1.to_string());
+
+                let register_compiler = Compiler::new();
+                let mut register_vm = RegisterMachine::new(&register_compiler.string_pool);
+                register_vm.set_arithmetic_mode(mode);
+                let register_result = register_vm.try_execute(&register_compiler.compile_registers(&parser.expressions))
+                    .map(|_| register_vm.variables())
+                    .map_err(|err| err.split_once(": ").unwrap().1.to_string());
+
+                assert_eq!(stack_result, register_result, "{} in {:?} mode", source, mode);
+            }
+        }
+    }
+}
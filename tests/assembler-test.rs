@@ -18,7 +18,41 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 #[cfg(test)]
 mod test {
+    use std::{fs, io::Write, process::{Command, Output, Stdio}};
+
     use ccil::*;
+    use ccil::asm::{Assembler, SourceLocation, expr::Expr};
+    use ccil::vm::{chunk::{Chunk, OperandEncoding}, opcode::OpCodeLookup, section::load_executable};
+
+    // assembles source with ccila, returning the process output and the written file
+    fn run_ccila(name: &str, source: &str, flags: &[&str]) -> (Output, Vec<u8>) {
+        let dir = std::env::temp_dir();
+        let input_path = dir.join(format!("ccila-test-{}.ccila", name));
+        let output_path = dir.join(format!("ccila-test-{}.ccilb", name));
+        fs::write(&input_path, source).unwrap();
+        let _ = fs::remove_file(&output_path);
+        let output = Command::new(env!("CARGO_BIN_EXE_ccila"))
+            .arg(&input_path)
+            .arg("-o").arg(&output_path)
+            .arg("--no-timestamp")
+            .args(flags)
+            .output()
+            .unwrap();
+        let chunk = fs::read(&output_path).unwrap_or_default();
+        (output, chunk)
+    }
+
+    fn operands(chunk: &Vec<u8>, encoding: OperandEncoding) -> Vec<(String, Vec<i32>)> {
+        let lookup = OpCodeLookup::new();
+        let mut retval = Vec::new();
+        let mut offset = 0;
+        while offset < chunk.len() {
+            let instruction = chunk.read_instruction(offset, encoding, &lookup).unwrap();
+            retval.push((instruction.opcode.symbol.to_string(), instruction.args));
+            offset = instruction.next_offset;
+        }
+        retval
+    }
 
     // TODO: Add actual tests
     #[test]
@@ -26,4 +60,356 @@ mod test {
         assert_eq!(constants::CCIL_MAGIC_BYTE_0, 0xCC);
         assert_eq!(constants::CCIL_MAGIC_BYTE_1, 0x17);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn labels_resolve_forward_and_backward() {
+        let source = "start: jump end\nloop:\n  nop\n  ifnz loop\nend:\n  call start\n";
+        for (name, flags, encoding) in [("labels", &[][..], OperandEncoding::Fixed), ("labels-compact", &["-c"][..], OperandEncoding::Compact)] {
+            let (output, file) = run_ccila(name, source, flags);
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            let code = load_executable(&file).unwrap().code;
+            let ops = operands(&code, encoding);
+            // loop follows the jump, and end follows the one-byte nop and the ifnz
+            let lookup = OpCodeLookup::new();
+            let loop_offset = code.read_instruction(0, encoding, &lookup).unwrap().next_offset;
+            let end_offset = code.read_instruction(loop_offset + 1, encoding, &lookup).unwrap().next_offset;
+            assert_eq!(ops[0], ("JUMP".to_string(), vec![end_offset as i32]));
+            assert_eq!(ops[2], ("IFNZ".to_string(), vec![loop_offset as i32]));
+            assert_eq!(ops[3], ("CALL".to_string(), vec![0]));
+        }
+    }
+
+    #[test]
+    fn undefined_and_duplicate_labels_are_errors() {
+        let (output, _) = run_ccila("undefined-label", "jump nowhere\n", &[]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("undefined label nowhere"));
+
+        let (output, _) = run_ccila("duplicate-label", "here: nop\nhere: nop\n", &[]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("duplicate name here"));
+    }
+
+    #[test]
+    fn strings_and_data_build_a_pool() {
+        let source = ".string greeting \"Hi // there\\n\"\n.data bytes 79 75 0\nconst greeting\nwrites 1\nconst bytes\nwrites 1\n";
+        let (output, _) = run_ccila("strings-execute", source, &["-e"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "Hi // there\n\nOK\n");
+
+        let (output, file) = run_ccila("strings", source, &[]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let executable = load_executable(&file).unwrap();
+        assert_eq!(executable.string_pool, b"Hi // there\n\0OK\0");
+        let ops = operands(&executable.code, OperandEncoding::Fixed);
+        assert_eq!(ops[2], ("CONST".to_string(), vec![13]));
+    }
+
+    #[test]
+    fn macros_constants_and_includes_expand() {
+        let dir = std::env::temp_dir();
+        fs::write(dir.join("ccila-test-macros-lib.ccila"), concat!(
+            ".equ START 3\n",
+            ".macro countdown n\n",
+            "    const n\n",
+            "loop\\@:\n",
+            "    copy 0\n",
+            "    write 1\n",
+            "    const 1\n",
+            "    sub\n",
+            "    copy 0\n",
+            "    ifnz loop\\@\n",
+            "    pop\n",
+            ".endm\n"
+        )).unwrap();
+        let source = ".include \"ccila-test-macros-lib.ccila\"\ncountdown START\ncountdown 1\n";
+        let (output, _) = run_ccila("macros", source, &["-e"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n2\n1\n1\n");
+    }
+
+    #[test]
+    fn include_cycles_and_macro_errors_report_their_origin() {
+        let dir = std::env::temp_dir();
+        fs::write(dir.join("ccila-test-cycle-a.ccila"), ".include \"ccila-test-cycle-b.ccila\"\n").unwrap();
+        fs::write(dir.join("ccila-test-cycle-b.ccila"), "nop\n.include \"ccila-test-cycle-a.ccila\"\n").unwrap();
+        let (output, _) = run_ccila("cycle", ".include \"ccila-test-cycle-a.ccila\"\n", &[]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("ccila-test-cycle-b.ccila:2:10: include cycle"), "{}", stderr);
+
+        let (output, _) = run_ccila("macro-error", "nop\n.macro bad\n    jump nowhere\n.endm\nbad\n", &[]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("ccila-test-macro-error.ccila:3:10: undefined label nowhere"), "{}", stderr);
+        assert!(stderr.contains("in macro bad expanded at"), "{}", stderr);
+        assert!(stderr.contains("ccila-test-macro-error.ccila:5:1"), "{}", stderr);
+    }
+
+    #[test]
+    fn assembler_reports_every_error_with_its_location() {
+        let assembler = Assembler::new();
+        let source = "cosnt 1\nconst 1 2\n  jump nowhere\nhere:\nhere: nop\n";
+        let errors = match assembler.assemble_source("errors.ccila", source) {
+            Ok(_) => panic!("expected errors"),
+            Err(errors) => errors
+        };
+        let summary: Vec<(usize, usize, &str, Option<&str>)> = errors.iter()
+            .map(|error| (error.location.line, error.location.column, error.token.as_str(), error.suggestion.as_deref()))
+            .collect();
+        assert_eq!(summary, vec![
+            (1, 1, "cosnt", Some("const")),
+            (2, 1, "const", None),
+            (3, 8, "nowhere", None),
+            (5, 1, "here:", None)
+        ]);
+        assert_eq!(errors[0].location, SourceLocation { file: "errors.ccila".to_string(), line: 1, column: 1 });
+        assert_eq!(errors[0].to_string(), "errors.ccila:1:1: unknown opcode cosnt (did you mean const?)");
+    }
+
+    #[test]
+    fn assembler_suggests_close_names() {
+        let assembler = Assembler::new();
+        let errors = assembler.assemble_source("typo.ccila", "loop:\n  jump lopo\n  sbu\n").err().unwrap();
+        assert_eq!(errors[0].suggestion.as_deref(), Some("loop"));
+        assert_eq!(errors[1].suggestion.as_deref(), Some("sub"));
+
+        let assembly = assembler.assemble_source("ok.ccila", "loop:\n  jump loop\n").ok().unwrap();
+        assert_eq!(assembly.code, vec![0x30, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn operands_accept_literals_names_and_expressions() {
+        let assembler = Assembler::new();
+        let source = concat!(
+            ".equ SIZE 4\n",
+            "start:\n",
+            "const 0x1f\n",
+            "const 0b101\n",
+            "const ' '\n",
+            "const '\\n'\n",
+            "const SIZE*2+1\n",
+            "const end-start\n",
+            "const -(SIZE+1)\n",
+            "store 0 number\n",
+            "write stderr\n",
+            "end:\n"
+        );
+        let assembly = match assembler.assemble_source("operands.ccila", source) {
+            Ok(val) => val,
+            Err(errors) => panic!("{}", errors[0])
+        };
+        let args: Vec<Vec<i32>> = operands(&assembly.code, OperandEncoding::Fixed).into_iter().map(|(_, args)| args).collect();
+        assert_eq!(args, vec![
+            vec![0x1f], vec![5], vec![32], vec![10], vec![9], vec![assembly.code.len() as i32], vec![-5],
+            vec![0, 1], vec![2]
+        ]);
+    }
+
+    #[test]
+    fn expressions_follow_precedence_and_reject_bad_addresses() {
+        let value = |text: &str| Expr::parse(text).unwrap().evaluate(&|_| None).unwrap().number;
+        assert_eq!(value("1+2*3"), 7);
+        assert_eq!(value("(1+2)*3"), 9);
+        assert_eq!(value("10-4-3"), 3);
+        assert_eq!(value("-2147483648"), i32::MIN as i64);
+        assert!(Expr::parse("1+").is_err());
+        assert!(Expr::parse("0xZ").is_err());
+
+        let errors = Assembler::new().assemble_source("bad.ccila", "here:\nconst here*2\nconst 1/0\n").err().unwrap();
+        assert_eq!(errors.iter().map(|error| error.message.as_str()).collect::<Vec<_>>(), vec![
+            "cannot apply * to an address",
+            "division by zero in expression"
+        ]);
+    }
+
+    // disassembles a file with ccild and reassembles the output with ccila
+    fn round_trip(name: &str, chunk: &[u8]) -> Vec<u8> {
+        let dir = std::env::temp_dir();
+        let bytecode_path = dir.join(format!("ccild-test-{}.ccilb", name));
+        let assembly_path = dir.join(format!("ccild-test-{}.ccila", name));
+        let output_path = dir.join(format!("ccild-test-{}-out.ccilb", name));
+        fs::write(&bytecode_path, chunk).unwrap();
+        let _ = fs::remove_file(&output_path);
+        let output = Command::new(env!("CARGO_BIN_EXE_ccild")).arg(&bytecode_path).arg("-o").arg(&assembly_path).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let output = Command::new(env!("CARGO_BIN_EXE_ccila")).arg(&assembly_path).arg("-o").arg(&output_path).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        fs::read(&output_path).unwrap()
+    }
+
+    #[test]
+    fn disassembly_reassembles_byte_for_byte() {
+        for entry in fs::read_dir("examples/bytecode").unwrap() {
+            let path = entry.unwrap().path();
+            let chunk = fs::read(&path).unwrap();
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            assert_eq!(round_trip(&name, &chunk), chunk, "{}", path.display());
+        }
+
+        // the other output formats, from the example assembly
+        let source = fs::read_to_string("examples/assembly/function.ccila").unwrap();
+        let source = format!(".string greeting \"hi\\n\"\nconst greeting\n{}", source.replace("function:", ".global function"));
+        for (name, flags) in [("compact", &["--compact"][..]), ("object", &["--object"][..]), ("sectioned", &[][..])] {
+            let (output, chunk) = run_ccila(&format!("round-trip-{}", name), &source, flags);
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            assert_eq!(round_trip(name, &chunk), chunk, "{}", name);
+        }
+
+        // and compiled source, which names its variables
+        for (name, flags) in [("source", &[][..]), ("source-object", &["--object"][..])] {
+            let output_path = std::env::temp_dir().join(format!("ccild-test-{}.ccilb", name));
+            let output = Command::new(env!("CARGO_BIN_EXE_ccil"))
+                .arg("examples/ccil_source_files/hello.ccil")
+                .arg("-o").arg(&output_path)
+                .args(flags)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            let chunk = fs::read(&output_path).unwrap();
+            assert_eq!(round_trip(&format!("{}-in", name), &chunk), chunk, "{}", name);
+        }
+    }
+
+    #[test]
+    fn disassembly_labels_branch_targets() {
+        let output = Command::new(env!("CARGO_BIN_EXE_ccild")).arg("examples/bytecode/function.ccilb").output().unwrap();
+        let assembly = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = assembly.lines().collect();
+        assert!(lines.contains(&"CALL label_41"), "{}", assembly);
+        assert!(lines.contains(&"label_41:"), "{}", assembly);
+        assert!(lines.contains(&"JUMP label_67"), "{}", assembly);
+        assert_eq!(lines.last(), Some(&"label_67:"));
+    }
+
+    #[test]
+    fn listing_shows_offsets_bytes_and_names() {
+        let source = concat!(
+            ".equ N 3\n",
+            ".macro twice x\n",
+            "const x\n",
+            "const x\n",
+            ".endm\n",
+            ".global main\n",
+            "    const N   // count\n",
+            "loop:\n",
+            "    twice 1\n",
+            "    ifnz loop\n"
+        );
+        let mut assembler = Assembler::new();
+        assembler.set_operand_encoding(OperandEncoding::Compact);
+        let listing = assembler.assemble_source("listing.ccila", source).ok().unwrap().listing();
+        assert_eq!(listing.lines().collect::<Vec<_>>(), vec![
+            "listing.ccila:",
+            "    1  0000                           .equ N 3",
+            "    6  0000                           .global main",
+            "    7  0000  01 06                    const N",
+            "    8  0002                           loop:",
+            "    9+ 0002  01 02                    const 1",
+            "    9+ 0004  01 02                    const 1",
+            "   10  0006  32 04                    ifnz loop",
+            "",
+            "NAME                 KIND      VALUE",
+            "N                    constant  3",
+            "loop                 label     2",
+            "main                 global    0"
+        ]);
+    }
+
+    #[test]
+    fn source_date_epoch_sets_the_header_timestamp() {
+        let dir = std::env::temp_dir();
+        let input_path = dir.join("ccila-test-epoch.ccila");
+        let output_path = dir.join("ccila-test-epoch.ccilb");
+        fs::write(&input_path, "nop\n").unwrap();
+        let ccila = |epoch: &str, flags: &[&str]| {
+            let _ = fs::remove_file(&output_path);
+            let output = Command::new(env!("CARGO_BIN_EXE_ccila"))
+                .arg(&input_path)
+                .arg("-o").arg(&output_path)
+                .args(flags)
+                .env("SOURCE_DATE_EPOCH", epoch)
+                .output()
+                .unwrap();
+            (output, fs::read(&output_path).unwrap_or_default())
+        };
+
+        let (output, chunk) = ccila("1769418713", &[]);
+        assert!(output.status.success());
+        assert_eq!(&chunk[6..10], &1769418713u32.to_le_bytes());
+        let (_, chunk) = ccila("1769418713", &["--timestamp", "42"]);
+        assert_eq!(&chunk[6..10], &42u32.to_le_bytes());
+
+        // the header holds an unsigned 32-bit timestamp
+        for (epoch, flags) in [("4294967296", &[][..]), ("0", &["--timestamp=-1"][..])] {
+            let (output, chunk) = ccila(epoch, flags);
+            assert!(!output.status.success());
+            assert!(chunk.is_empty());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("does not fit in a header"));
+        }
+    }
+
+    #[test]
+    fn objects_cannot_use_compact_operands() {
+        let mut assembler = Assembler::new();
+        assembler.set_object(true);
+        assembler.set_operand_encoding(OperandEncoding::Compact);
+        let errors = assembler.assemble_source("object.ccila", "nop\n").err().unwrap();
+        assert_eq!(errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(), vec![
+            "object.ccila:1:1: object chunks require fixed operand encoding"
+        ]);
+    }
+
+    #[test]
+    fn unreadable_files_are_reported_at_their_first_line() {
+        let path = std::env::temp_dir().join("ccila-test-missing.ccila");
+        let errors = Assembler::new().assemble_file(&path).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        let prefix = format!("{}:1:1: failed to read", path.display());
+        assert!(errors[0].to_string().starts_with(&prefix), "{}", errors[0]);
+    }
+
+    #[test]
+    fn labels_that_never_settle_are_errors() {
+        // end-67 takes two bytes while `end` is 2 and one byte while it is 3
+        let mut assembler = Assembler::new();
+        assembler.set_operand_encoding(OperandEncoding::Compact);
+        let errors = assembler.assemble_source("oscillate.ccila", "const end-67\nend:\n").err().unwrap();
+        assert_eq!(errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(), vec![
+            "oscillate.ccila:2:1: label layout did not converge"
+        ]);
+    }
+
+    #[test]
+    fn interactive_mode_keeps_state_between_lines() {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ccila"))
+            .arg("--interactive")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let session = ".equ N 5\nconst N\nconst 3\nsub\npop\npop\nfoo\nconst 7\nstore 1 number\n";
+        child.stdin.take().unwrap().write_all(session.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let states: Vec<&str> = stdout.split("ccila> ").filter(|state| !state.is_empty()).map(|state| state.trim_end()).collect();
+        assert_eq!(states, vec![
+            "stack: []",
+            "stack: [5]",
+            "stack: [5, 3]",
+            "stack: [2]",
+            "stack: []",
+            "stack: []",
+            "stack: [7]",
+            "stack: []\nvar 1: Number(7)"
+        ]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(stderr.lines().collect::<Vec<_>>(), vec![
+            "Error at chunk offset 0: stack underflow at offset 0",
+            "Error at column 1: unknown opcode foo"
+        ]);
+    }
+}
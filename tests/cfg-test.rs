@@ -0,0 +1,90 @@
+/*
+cfg-test.rs: Tests for CCIL control-flow graphs
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use petgraph::visit::EdgeRef;
+
+    use ccil::asm::Assembler;
+    use ccil::vm::cfg::{self, ControlFlowGraph, EdgeKind};
+    use ccil::vm::chunk::OperandEncoding;
+    use ccil::vm::opcode::OpCodeLookup;
+
+    // every edge as (from block start, to block start, kind), in a stable order
+    fn edges(graph: &ControlFlowGraph) -> Vec<(usize, usize, EdgeKind)> {
+        let mut edges: Vec<_> = graph.edge_references()
+            .map(|edge| (graph[edge.source()].start, graph[edge.target()].start, *edge.weight()))
+            .collect();
+        edges.sort_by_key(|(from, to, _)| (*from, *to));
+        edges
+    }
+
+    #[test]
+    fn blocks_split_at_targets_and_after_branches() {
+        let source = concat!(
+            "const 3\n",        // 0
+            "loop:\n",
+            "const 1\n",        // 5
+            "sub\n",            // 10
+            "copy 0\n",         // 11
+            "ifnz loop\n",      // 16
+            "call function\n",  // 21
+            "jump end\n",       // 26
+            "function:\n",
+            "return 0\n",       // 31
+            "end:\n"
+        );
+        let assembly = Assembler::new().assemble_source("cfg.ccila", source).unwrap();
+        let lookup = OpCodeLookup::new();
+        let graph = cfg::from_chunk(&assembly.code, OperandEncoding::Fixed, &lookup).unwrap();
+
+        let starts: Vec<usize> = graph.node_weights().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 5, 21, 26, 31]);
+        assert_eq!(graph.node_weights().map(|block| block.instructions.len()).collect::<Vec<_>>(), vec![1, 4, 1, 1, 1]);
+        assert_eq!(edges(&graph), vec![
+            (0, 5, EdgeKind::Fallthrough),
+            (5, 5, EdgeKind::Taken),
+            (5, 21, EdgeKind::Fallthrough),
+            (21, 26, EdgeKind::Fallthrough),
+            (21, 31, EdgeKind::Call)
+        ]);
+    }
+
+    #[test]
+    fn superinstruction_branches_split_blocks() {
+        let source = "const 3\nloop:\naddc -1\ncopyifnz loop\npop\n";
+        let assembly = Assembler::new().assemble_source("fused.ccila", source).unwrap();
+        let lookup = OpCodeLookup::new();
+        let graph = cfg::from_chunk(&assembly.code, OperandEncoding::Fixed, &lookup).unwrap();
+        assert_eq!(edges(&graph), vec![
+            (0, 5, EdgeKind::Fallthrough),
+            (5, 5, EdgeKind::Taken),
+            (5, 15, EdgeKind::Fallthrough)
+        ]);
+    }
+
+    #[test]
+    fn dot_lists_instructions_and_edge_kinds() {
+        let assembly = Assembler::new().assemble_source("dot.ccila", "top:\nconst 0\nifz top\n").unwrap();
+        let lookup = OpCodeLookup::new();
+        let dot = cfg::to_dot(&cfg::from_chunk(&assembly.code, OperandEncoding::Fixed, &lookup).unwrap());
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("label = \"0:\\lCONST 0\\lIFZ 0\\l\""), "{}", dot);
+        assert!(dot.contains("0 -> 0 [ label = \"taken\" ]"), "{}", dot);
+    }
+}
@@ -0,0 +1,216 @@
+/*
+chunk-test.rs: Tests for CCIL bytecode chunks
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use ccil::constants::header_flag_const;
+    use ccil::vm::chunk::{Chunk, ChunkError, OperandEncoding};
+    use ccil::vm::hexdump::hexdump;
+    use ccil::vm::opcode::OpCodeLookup;
+    use ccil::vm::section::{Executable, SymbolTable, decode_sections, load_executable, section_id_const, symbol_id_const};
+
+    #[test]
+    fn read_arg_round_trips() {
+        let mut chunk = Vec::<u8>::new();
+        chunk.write_arg(-123456);
+        assert_eq!(chunk.read_arg(0).unwrap(), -123456);
+    }
+
+    #[test]
+    fn read_arg_past_end_reports_offset() {
+        let chunk: Vec<u8> = vec![0x01, 0x02, 0x00];
+        match chunk.read_arg(1) {
+            Err(ChunkError::UnexpectedEnd { offset, needed, len }) => {
+                assert_eq!((offset, needed, len), (1, 4, 3));
+            },
+            other => panic!("Expected UnexpectedEnd, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn from_file_reports_path() {
+        let path = "this/path/does/not/exist.ccilb";
+        let error = Vec::<u8>::from_file(path).unwrap_err();
+        assert!(matches!(error, ChunkError::Read { .. }));
+        assert!(error.to_string().contains(path));
+    }
+
+    #[test]
+    fn without_header_rejects_truncated_header() {
+        let chunk: Vec<u8> = vec![0xCC, 0x17, 0x00, 0x01];
+        assert!(matches!(chunk.without_header(), Err(ChunkError::UnexpectedEnd { offset: 0, .. })));
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let mut chunk: Vec<u8> = vec![0x00, 0x02];
+        let with_header = chunk.with_header(header_flag_const::FROM_ASSEMBLY, 0);
+        assert_eq!(with_header.without_header().unwrap(), vec![0x00, 0x02]);
+    }
+
+    #[test]
+    fn compact_operands_round_trip() {
+        for value in [0, 1, -1, 63, -64, 64, 300, -300, i32::MAX, i32::MIN] {
+            let mut chunk = Vec::<u8>::new();
+            chunk.write_operand(value, OperandEncoding::Compact);
+            let (decoded, size) = chunk.read_operand(0, OperandEncoding::Compact).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(size, chunk.len());
+        }
+    }
+
+    #[test]
+    fn compact_operands_are_small() {
+        let mut chunk = Vec::<u8>::new();
+        chunk.write_operand(1, OperandEncoding::Compact);
+        chunk.write_operand(-64, OperandEncoding::Compact);
+        assert_eq!(chunk.len(), 2);
+    }
+
+    #[test]
+    fn compact_operand_overflow_is_rejected() {
+        let chunk: Vec<u8> = vec![0xFF, 0xFF, 0xFF, 0xFF, 0x7F];
+        assert!(matches!(chunk.read_operand(0, OperandEncoding::Compact), Err(ChunkError::OperandOverflow { offset: 0 })));
+    }
+
+    #[test]
+    fn read_instruction_agrees_across_encodings() {
+        let lookup = OpCodeLookup::new();
+        let store = lookup.from_symbol("STORE").unwrap();
+        for encoding in [OperandEncoding::Fixed, OperandEncoding::Compact] {
+            let mut chunk = Vec::<u8>::new();
+            chunk.write_op(store);
+            chunk.write_operand(3, encoding);
+            chunk.write_operand(-1, encoding);
+            let instruction = chunk.read_instruction(0, encoding, &lookup).unwrap();
+            assert_eq!(instruction.opcode.symbol, "STORE");
+            assert_eq!(instruction.args, vec![3, -1]);
+            assert_eq!(instruction.next_offset, chunk.len());
+        }
+    }
+
+    #[test]
+    fn read_instruction_rejects_unknown_opcode() {
+        let lookup = OpCodeLookup::new();
+        let chunk: Vec<u8> = vec![0x00, 0xFF];
+        assert!(matches!(
+            chunk.read_instruction(1, OperandEncoding::Fixed, &lookup),
+            Err(ChunkError::UnknownOpcode { offset: 1, byte: 0xFF })
+        ));
+    }
+
+    #[test]
+    fn header_records_operand_encoding() {
+        assert_eq!(vec![0x00].with_header(OperandEncoding::Compact.header_flag(), 0).operand_encoding(), OperandEncoding::Compact);
+        assert_eq!(vec![0x00].with_header(OperandEncoding::Fixed.header_flag(), 0).operand_encoding(), OperandEncoding::Fixed);
+        assert_eq!(vec![0x00].operand_encoding(), OperandEncoding::Fixed);
+    }
+
+    #[test]
+    fn symbol_section_round_trips() {
+        let executable = Executable {
+            code: vec![0x00],
+            string_pool: b"a\0".to_vec(),
+            symbols: SymbolTable {
+                functions: vec![("main".to_string(), 0)],
+                variables: vec![("x".to_string(), 0), ("y".to_string(), 1)]
+            },
+            encoding: OperandEncoding::Fixed
+        };
+        let loaded = load_executable(&executable.to_chunk(0, 0)).unwrap();
+        assert_eq!(loaded.symbols, executable.symbols);
+        assert_eq!(loaded.symbols.function("main"), Some(0));
+        assert_eq!(loaded.symbols.variable("y"), Some(1));
+    }
+
+    #[test]
+    fn malformed_section_lengths_are_rejected() {
+        let mut body = vec![0x01];
+        body.write_arg(-1);
+        body.push(0x00);
+        assert!(matches!(decode_sections(&body), Err(ChunkError::MalformedSection { section: 0x01, offset: 0 })));
+
+        let mut body = vec![0x01];
+        body.write_arg(i32::MAX);
+        assert!(matches!(decode_sections(&body), Err(ChunkError::UnexpectedEnd { offset: 5, .. })));
+    }
+
+    #[test]
+    fn malformed_symbol_names_are_rejected() {
+        let mut data = vec![symbol_id_const::FUNCTION];
+        data.write_arg(-4);
+        data.write_arg(0);
+        assert!(matches!(SymbolTable::decode(&data), Err(ChunkError::MalformedSection { section: section_id_const::SYMBOLS, offset: 1 })));
+
+        let mut data = vec![symbol_id_const::VARIABLE];
+        data.write_arg(i32::MAX);
+        assert!(matches!(SymbolTable::decode(&data), Err(ChunkError::UnexpectedEnd { offset: 5, .. })));
+
+        // a function name followed by a negative offset
+        let mut data = vec![symbol_id_const::FUNCTION];
+        data.write_arg(0);
+        data.write_arg(-1);
+        assert!(matches!(SymbolTable::decode(&data), Err(ChunkError::MalformedSection { section: section_id_const::SYMBOLS, offset: 0 })));
+    }
+
+    #[test]
+    fn hexdump_annotates_header_and_instructions() {
+        let lookup = OpCodeLookup::new();
+        let mut code = Vec::<u8>::new();
+        code.write_op(lookup.from_symbol("NOP").unwrap());
+        code.write_op(lookup.from_symbol("CONST").unwrap());
+        code.write_arg(2);
+        code.write_op(lookup.from_symbol("POP").unwrap());
+        let chunk = code.with_header(header_flag_const::FROM_ASSEMBLY, 1769418713);
+        let dump = hexdump(&chunk, &lookup);
+        let lines: Vec<&str> = dump.lines().collect();
+        let (major, minor, patch) = ccil::version();
+        assert_eq!(lines[0], ".================ HEADER ================.");
+        assert_eq!(lines[1], "CC 17                        -- magic num");
+        assert_eq!(lines[2], format!("{:02X} {:02X} {:02X}                     -- v{}.{}.{}", major, minor, patch, major, minor, patch));
+        assert_eq!(lines[3..], [
+            "01                           -- bitflags: assembly",
+            "D9 2F 77 69                  -- timestamp 2026-01-26 09:11:53 UTC",
+            "00 00 00 00 00 00            -- padding",
+            "'========================================'",
+            ".================ PROGRAM ===============.",
+            "00                           -- 0000: nop",
+            "01 02000000                  -- 0001: const 2",
+            "02                           -- 0006: pop",
+            "'========================================'"
+        ]);
+    }
+
+    #[test]
+    fn hexdump_lists_sections_and_survives_corruption() {
+        let lookup = OpCodeLookup::new();
+        let executable = Executable {
+            code: vec![0x00],
+            string_pool: b"hi\0".to_vec(),
+            symbols: SymbolTable { functions: vec![("main".to_string(), 0)], variables: Vec::new() },
+            encoding: OperandEncoding::Fixed
+        };
+        let dump = hexdump(&executable.to_chunk(0, 0), &lookup);
+        assert!(dump.contains("bitflags: source, sectioned"), "{}", dump);
+        assert!(dump.contains("68 69 00                     -- 0000: \"hi\""), "{}", dump);
+        assert!(dump.contains("00 04000000 6D61696E 00000000 -- function main @ 0"), "{}", dump);
+
+        let dump = hexdump(&vec![0x00, 0xff, 0x01], &lookup);
+        assert!(dump.contains("FF 01                        -- Unknown opcode with value 0xff at offset 1"), "{}", dump);
+    }
+}
@@ -0,0 +1,63 @@
+/*
+compiler-test.rs: Tests for the CCIL Bytecode Compiler
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use ccil::compiler::Compiler;
+    use ccil::parser::{Parser, token::Token};
+    use ccil::vm::chunk::{Chunk, ChunkError, OperandEncoding, resolve_timestamp};
+    use ccil::vm::section::Executable;
+
+    const SOURCE: &str = "greeting = \"hi\"; b = 2; a = 1; name = \"ccil\"; print(greeting); print(name); print(a);";
+
+    fn build(timestamp: i64) -> Vec<u8> {
+        let mut parser = Parser::new(Token::full_scan(SOURCE));
+        parser.full_parse();
+        let compiler = Compiler::new();
+        let code = compiler.compile(&parser.expressions);
+        let executable = Executable {
+            code,
+            string_pool: compiler.string_pool.borrow().clone(),
+            symbols: compiler.symbol_table(),
+            encoding: OperandEncoding::Fixed
+        };
+        executable.to_chunk(0, timestamp)
+    }
+
+    #[test]
+    fn identical_input_gives_identical_bytecode() {
+        let first = build(1_700_000_000);
+        for _ in 0..8 {
+            assert_eq!(build(1_700_000_000), first);
+        }
+    }
+
+    #[test]
+    fn header_stores_given_timestamp() {
+        let chunk = vec![0x00].with_header(0, 0x01020304);
+        assert_eq!(&chunk[6..10], &[0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(&build(0)[6..10], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn explicit_timestamp_wins() {
+        assert_eq!(resolve_timestamp(Some(42)).unwrap(), 42);
+        assert!(matches!(resolve_timestamp(Some(-1)), Err(ChunkError::TimestampOutOfRange(-1))));
+        assert!(matches!(resolve_timestamp(Some(1 << 32)), Err(ChunkError::TimestampOutOfRange(_))));
+    }
+}
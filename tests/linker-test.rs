@@ -0,0 +1,185 @@
+/*
+linker-test.rs: Tests for the CCIL Linker
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use ccil::compiler::Compiler;
+    use ccil::linker::{LinkError, ObjectChunk, Relocation, RelocationKind, link};
+    use ccil::parser::{Parser, token::Token};
+    use ccil::vm::chunk::{Chunk, ChunkError, OperandEncoding};
+    use ccil::vm::opcode::OpCodeLookup;
+    use ccil::vm::section::{load_executable, section_id_const};
+
+    fn assemble(ops: &[(&str, &[i32])]) -> Vec<u8> {
+        let lookup = OpCodeLookup::new();
+        let mut chunk = Vec::<u8>::new();
+        for (symbol, args) in ops {
+            chunk.write_op(lookup.from_symbol(symbol).unwrap());
+            for arg in *args {
+                chunk.write_arg(*arg);
+            }
+        }
+        chunk
+    }
+
+    fn compile_object(source: &str, name: &str) -> ObjectChunk {
+        let mut parser = Parser::new(Token::full_scan(source));
+        parser.full_parse();
+        Compiler::new().compile_object(&parser.expressions, name).unwrap()
+    }
+
+    #[test]
+    fn object_round_trips_through_chunk() {
+        let object = ObjectChunk {
+            code: assemble(&[("CALL", &[0]), ("CONST", &[2])]),
+            string_pool: b"hi\0".to_vec(),
+            exports: vec![("entry".to_string(), 0)],
+            relocations: vec![
+                Relocation { offset: 1, kind: RelocationKind::Symbol("helper".to_string()) },
+                Relocation { offset: 6, kind: RelocationKind::String }
+            ]
+        };
+        assert_eq!(ObjectChunk::from_chunk(&object.to_chunk(0, 0)).unwrap(), object);
+    }
+
+    #[test]
+    fn link_patches_code_and_symbols() {
+        let main = ObjectChunk {
+            code: assemble(&[("CALL", &[0]), ("JUMP", &[5])]),
+            relocations: vec![
+                Relocation { offset: 1, kind: RelocationKind::Symbol("helper".to_string()) },
+                Relocation { offset: 6, kind: RelocationKind::Code }
+            ],
+            ..Default::default()
+        };
+        let helper = ObjectChunk {
+            code: assemble(&[("NOP", &[]), ("RETURN", &[0])]),
+            exports: vec![("helper".to_string(), 1)],
+            ..Default::default()
+        };
+        let program = link(&[("main".to_string(), main), ("helper".to_string(), helper)]).unwrap();
+
+        assert_eq!(program.symbols["helper"], 11);
+        assert_eq!(program.code.read_arg(1).unwrap(), 11);
+        assert_eq!(program.code.read_arg(6).unwrap(), 5);
+    }
+
+    #[test]
+    fn link_keeps_module_strings_and_variables_apart() {
+        let first = compile_object("x = \"one\"; print(x);", "first");
+        let second = compile_object("y = \"two\"; print(y);", "second");
+        let program = link(&[("first".to_string(), first), ("second".to_string(), second)]).unwrap();
+
+        assert_eq!(program.string_pool, b"one\0two\0".to_vec());
+        // second module: CONST <string>, STORE <var> <type>
+        let second_start = program.symbols["second"];
+        assert_eq!(program.code.read_arg(second_start + 1).unwrap(), 4);
+        assert_eq!(program.code.read_arg(second_start + 6).unwrap(), 1);
+    }
+
+    #[test]
+    fn objects_cannot_use_compact_operands() {
+        let mut parser = Parser::new(Token::full_scan("print(1);"));
+        parser.full_parse();
+        let mut compiler = Compiler::new();
+        compiler.set_operand_encoding(OperandEncoding::Compact);
+        assert!(matches!(compiler.compile_object(&parser.expressions, "main"), Err(ChunkError::CompactObject)));
+    }
+
+    #[test]
+    fn link_reports_undefined_and_duplicate_symbols() {
+        let importer = ObjectChunk {
+            code: assemble(&[("CALL", &[0])]),
+            relocations: vec![Relocation { offset: 1, kind: RelocationKind::Symbol("missing".to_string()) }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            link(&[("importer".to_string(), importer)]),
+            Err(LinkError::UndefinedSymbol { .. })
+        ));
+
+        let exporter = ObjectChunk { code: vec![0x00], exports: vec![("f".to_string(), 0)], ..Default::default() };
+        assert!(matches!(
+            link(&[("a".to_string(), exporter.clone()), ("b".to_string(), exporter)]),
+            Err(LinkError::DuplicateSymbol { .. })
+        ));
+    }
+
+    #[test]
+    fn malformed_exports_and_relocations_are_rejected() {
+        // negative offsets are written as usize::MAX
+        let export = ObjectChunk { code: vec![0x00], exports: vec![("f".to_string(), usize::MAX)], ..Default::default() };
+        assert!(matches!(
+            ObjectChunk::from_chunk(&export.to_chunk(0, 0)),
+            Err(ChunkError::MalformedSection { section: section_id_const::EXPORTS, .. })
+        ));
+
+        let relocation = ObjectChunk {
+            code: vec![0x00],
+            relocations: vec![Relocation { offset: usize::MAX, kind: RelocationKind::Code }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            ObjectChunk::from_chunk(&relocation.to_chunk(0, 0)),
+            Err(ChunkError::MalformedSection { section: section_id_const::RELOCATIONS, offset: 0 })
+        ));
+        assert!(matches!(link(&[("main".to_string(), relocation)]), Err(LinkError::BadRelocation { .. })));
+    }
+
+    #[test]
+    fn link_reports_operands_that_overflow() {
+        let padding = ObjectChunk { code: vec![0x00], ..Default::default() };
+        let jump = ObjectChunk {
+            code: assemble(&[("JUMP", &[i32::MAX])]),
+            relocations: vec![Relocation { offset: 1, kind: RelocationKind::Code }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            link(&[("padding".to_string(), padding), ("jump".to_string(), jump)]),
+            Err(LinkError::Overflow { offset: 1, .. })
+        ));
+
+        let variable = ObjectChunk {
+            code: assemble(&[("LOAD", &[i32::MAX])]),
+            relocations: vec![Relocation { offset: 1, kind: RelocationKind::Variable }],
+            ..Default::default()
+        };
+        assert!(matches!(link(&[("variable".to_string(), variable)]), Err(LinkError::Overflow { offset: 1, .. })));
+    }
+
+    #[test]
+    fn linked_program_loads_as_executable() {
+        let object = compile_object("print(\"hi\");", "main");
+        let program = link(&[("main".to_string(), object)]).unwrap();
+        let code = program.code.clone();
+        let executable = load_executable(&program.to_executable().to_chunk(0, 0)).unwrap();
+        assert_eq!(executable.code, code);
+        assert_eq!(executable.string_pool, b"hi\0".to_vec());
+        assert_eq!(executable.symbols.function("main"), Some(0));
+    }
+
+    #[test]
+    fn compiler_names_variables_in_id_order() {
+        let mut parser = Parser::new(Token::full_scan("b = 1; a = 2; print(b);"));
+        parser.full_parse();
+        let compiler = Compiler::new();
+        compiler.compile(&parser.expressions);
+        let symbols = compiler.symbol_table();
+        assert_eq!(symbols.variables, vec![("b".to_string(), 0), ("a".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,246 @@
+/*
+optimizer-test.rs: Tests for the CCIL peephole optimizer
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use ccil::asm::Assembler;
+    use ccil::compiler::Compiler;
+    use ccil::linker::{Relocation, RelocationKind, link};
+    use ccil::optimizer::{fold::fold_constants, optimize, optimize_executable, optimize_object};
+    use ccil::parser::{Parser, expr::Expr, token::Token};
+    use ccil::vm::VirtualMachine;
+    use ccil::vm::chunk::{ChunkError, OperandEncoding};
+    use ccil::vm::opcode::OpCodeLookup;
+    use ccil::vm::section::{Executable, SymbolTable};
+    use ccil::vm::stack::Stack;
+    use ccil::vm::variable_value::VariableValue;
+
+    fn code(source: &str) -> Vec<u8> {
+        Assembler::new().assemble_source("test.ccila", source).unwrap().code
+    }
+
+    fn parse(source: &str) -> Vec<Expr> {
+        let mut parser = Parser::new(Token::full_scan(source));
+        parser.full_parse();
+        parser.expressions
+    }
+
+    fn optimized(source: &str, encoding: OperandEncoding) -> Vec<u8> {
+        let mut assembler = Assembler::new();
+        assembler.set_operand_encoding(encoding);
+        let assembly = assembler.assemble_source("test.ccila", source).unwrap();
+        optimize(&assembly.code, encoding, &[], &[], &OpCodeLookup::new()).unwrap().code
+    }
+
+    #[test]
+    fn constants_fold_through_chains() {
+        let source = "const 1\nconst 2\nadd\nconst 3\nmul\nneg\nconst 4\nsub\nwrite 1\n";
+        assert_eq!(optimized(source, OperandEncoding::Fixed), code("const -13\nwrite 1\n"));
+    }
+
+    #[test]
+    fn folding_keeps_runtime_errors() {
+        for source in ["const 1\nconst 0\ndiv\n", "const -2147483648\nconst -1\ndiv\n", "const 1\nconst 40\nshl\n"] {
+            assert_eq!(optimized(source, OperandEncoding::Fixed), code(source));
+        }
+    }
+
+    #[test]
+    fn store_then_load_becomes_copy() {
+        assert_eq!(optimized("const 5\nstore 0 1\nload 0\n", OperandEncoding::Fixed), code("const 5\ncopy 0\nstore 0 1\n"));
+        // booleans load back as 0 or 1, not the value stored
+        assert_eq!(optimized("const 5\nstore 0 4\nload 0\n", OperandEncoding::Fixed), code("const 5\nstore 0 4\nload 0\n"));
+    }
+
+    #[test]
+    fn jumps_are_threaded_and_nops_removed() {
+        let source = concat!(
+            "ifz middle\n",
+            "nop\n",
+            "middle:\n",
+            "jump last\n",
+            "nop\n",
+            "last:\n",
+            "nop\n",
+            "const 1\n"
+        );
+        let expected = concat!(
+            "ifz last\n",
+            "jump last\n",
+            "last:\n",
+            "const 1\n"
+        );
+        assert_eq!(optimized(source, OperandEncoding::Fixed), code(expected));
+        assert_eq!(optimized(source, OperandEncoding::Compact), {
+            let mut assembler = Assembler::new();
+            assembler.set_operand_encoding(OperandEncoding::Compact);
+            assembler.assemble_source("expected.ccila", expected).unwrap().code
+        });
+    }
+
+    #[test]
+    fn patterns_do_not_span_branch_targets() {
+        let source = "const 1\nagain:\nconst 2\nadd\njump again\n";
+        assert_eq!(optimized(source, OperandEncoding::Fixed), code("const 1\nagain:\naddc 2\njump again\n"));
+        let source = "load 0\nconst 1\nmiddle:\nadd\nstore 0 1\ncopy 0\nend:\nifz middle\njump end\n";
+        assert_eq!(optimized(source, OperandEncoding::Fixed), code(source));
+    }
+
+    #[test]
+    fn hot_pairs_become_superinstructions() {
+        let source = concat!(
+            "const 0\n",
+            "store 0 1\n",
+            "loop:\n",
+            "load 0\n",
+            "const 1\n",
+            "add\n",
+            "store 0 1\n",
+            "load 0\n",
+            "const 10\n",
+            "sub\n",
+            "copy 0\n",
+            "ifz done\n",
+            "pop\n",
+            "jump loop\n",
+            "done:\n",
+            "pop\n"
+        );
+        let expected = concat!(
+            "const 0\n",
+            "store 0 1\n",
+            "loop:\n",
+            "incvar 0 1\n",
+            "load 0\n",
+            "addc -10\n",
+            "copyifz done\n",
+            "pop\n",
+            "jump loop\n",
+            "done:\n",
+            "pop\n"
+        );
+        let fused = optimized(source, OperandEncoding::Fixed);
+        assert_eq!(fused, code(expected));
+
+        for chunk in [code(source), fused] {
+            let string_pool = RefCell::new(Vec::new());
+            let mut vm = VirtualMachine::new(&string_pool);
+            vm.try_execute(&chunk).unwrap();
+            assert_eq!(vm.variables()[&0], VariableValue::Number(10));
+            assert!(vm.stack().is_empty());
+        }
+    }
+
+    #[test]
+    fn superinstructions_keep_types_and_overflow() {
+        // booleans are stored as booleans, so the store stays separate
+        assert_eq!(optimized("load 0\nconst 1\nadd\nstore 0 4\n", OperandEncoding::Fixed), code("load 0\naddc 1\nstore 0 4\n"));
+        let source = "load 0\nconst -2147483648\nsub\n";
+        assert_eq!(optimized(source, OperandEncoding::Fixed), code(source));
+        assert_eq!(optimized("const 2147483647\nconst 5\nadd\n", OperandEncoding::Fixed), code("const 2147483647\naddc 5\n"));
+        assert_eq!(optimized("const 2\naddc 3\n", OperandEncoding::Fixed), code("const 5\n"));
+    }
+
+    #[test]
+    fn misaligned_branches_are_rejected() {
+        let error = optimize(&code("jump 2\nconst 0\n"), OperandEncoding::Fixed, &[], &[], &OpCodeLookup::new()).err();
+        assert!(matches!(error, Some(ChunkError::MisalignedBranch { offset: 0, target: 2 })));
+    }
+
+    #[test]
+    fn optimized_compiler_output_runs_the_same() {
+        let mut parser = Parser::new(Token::full_scan("a = 2 + 3; print(a); b = 4 - 1; print(a + b);"));
+        parser.full_parse();
+        let mut compiler = Compiler::new();
+        let plain = compiler.compile(&parser.expressions);
+        compiler.set_optimize(true);
+        let code = compiler.compile(&parser.expressions);
+        assert!(code.len() < plain.len());
+
+        for chunk in [plain, code] {
+            let string_pool = RefCell::new(Vec::new());
+            let mut vm = VirtualMachine::new(&string_pool);
+            vm.try_execute(&chunk).unwrap();
+            assert_eq!(vm.variables().len(), 2);
+            assert!(vm.stack().is_empty());
+        }
+    }
+
+    #[test]
+    fn executables_keep_code_that_constants_point_at() {
+        let executable = |source: &str| Executable {
+            code: code(source),
+            string_pool: Vec::new(),
+            symbols: SymbolTable::default(),
+            encoding: OperandEncoding::Fixed
+        };
+        let lookup = OpCodeLookup::new();
+
+        // nothing marks `const back` as an address, so removing the NOPs would strand it
+        let source = "nop\nnop\nconst back\njump f\nback:\njump end\nf:\nreturn 0\nend:\n";
+        assert_eq!(optimize_executable(&executable(source), &lookup).unwrap().code, code(source));
+
+        let optimized = optimize_executable(&executable("nop\nconst 100\npop\n"), &lookup).unwrap();
+        assert_eq!(optimized.code, code("const 100\npop\n"));
+    }
+
+    #[test]
+    fn objects_keep_exports_and_relocations() {
+        let mut parser = Parser::new(Token::full_scan("x = 1 + 1; y = \"hi\"; print(y);"));
+        parser.full_parse();
+        let object = Compiler::new().compile_object(&parser.expressions, "main").unwrap();
+        let optimized = optimize_object(&object, &OpCodeLookup::new()).unwrap();
+        assert!(optimized.code.len() < object.code.len());
+        assert_eq!(optimized.exports, object.exports);
+        let strings = |relocations: &[Relocation]| relocations.iter().filter(|relocation| relocation.kind == RelocationKind::String).count();
+        assert_eq!(strings(&optimized.relocations), strings(&object.relocations));
+
+        let program = link(&[("main".to_string(), optimized)]).unwrap();
+        let string_pool = RefCell::new(program.string_pool);
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.try_execute(&program.code).unwrap();
+    }
+
+    #[test]
+    fn constant_expressions_fold_to_literals() {
+        let folded = fold_constants(parse("(1+2)+3+4+(5*6-3*12)+166-(12<<1|4); a = -(2*3) > ~0; b = !(true && false);"));
+        assert_eq!(folded, parse("142; a = false; b = true;"));
+    }
+
+    #[test]
+    fn folding_keeps_runtime_errors_and_variables() {
+        let folded = fold_constants(parse("a = 1/0; b = 2147483647+1; c = 7%(2-2);"));
+        assert_eq!(folded, parse("a = 1/0; b = 2147483647+1; c = 7%0;"));
+        assert_eq!(fold_constants(parse("y = (x*1+0)/1 + (0+x)*(1*x);")), parse("y = x + x*x;"));
+        assert_eq!(fold_constants(parse("s = \"a\" + 0;")), parse("s = \"a\" + 0;"));
+    }
+
+    #[test]
+    fn false_conditions_prune_statements() {
+        let source = concat!(
+            "if(1 > 2) { print(1); };",
+            "while(false) { print(2); };",
+            "func f(a) { if(null) { print(3); }; return a; };",
+            "if(2 > 1) { print(4); };"
+        );
+        let expected = "func f(a) { return a; }; if(true) { print(4); };";
+        assert_eq!(fold_constants(parse(source)), parse(expected));
+    }
+}
@@ -0,0 +1,50 @@
+/*
+parser-test.rs: Tests for the CCIL tokenizer and parser
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use ccil::parser::{Parser, expr::Expr, token::Token};
+
+    fn scan(source: &str) -> Vec<Token> {
+        // full_scan returns tokens last first, for use as a stack
+        let mut tokens = Token::full_scan(source);
+        tokens.reverse();
+        tokens
+    }
+
+    #[test]
+    fn comparison_and_shift_operators_scan_to_their_own_tokens() {
+        use Token::*;
+        assert_eq!(scan("< <= << > >= >>"), vec![
+            LessThan, LessThanEqual, DoubleLessThan,
+            GreaterThan, GreaterThanEqual, DoubleGreaterThan,
+            EOF
+        ]);
+    }
+
+    #[test]
+    fn comparisons_keep_their_direction_when_parsed() {
+        let mut parser = Parser::new(Token::full_scan("a < b;"));
+        parser.full_parse();
+        let Expr::Binary(token, left, _) = &parser.expressions[0] else {
+            panic!("expected a binary expression, got {:?}", parser.expressions[0]);
+        };
+        assert_eq!(*token, Token::LessThan);
+        assert_eq!(left.get_token(), &Token::VarName("a".to_string()));
+    }
+}
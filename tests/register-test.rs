@@ -0,0 +1,77 @@
+/*
+register-test.rs: Tests for the CCIL register machine
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use ccil::compiler::Compiler;
+    use ccil::optimizer::fold::fold_constants;
+    use ccil::parser::{Parser, expr::Expr, token::Token};
+    use ccil::vm::VirtualMachine;
+    use ccil::vm::register::{RegisterMachine, RegisterOp};
+
+    fn parse(source: &str) -> Vec<Expr> {
+        let mut parser = Parser::new(Token::full_scan(source));
+        parser.full_parse();
+        fold_constants(parser.expressions)
+    }
+
+    #[test]
+    fn engines_agree_on_variables() {
+        let expressions = parse("a = 2; b = a * 10 - 3; c = (a + b) * (b - 1) % 7; s = \"hi\"; t = true; n = null; b = b + 1; d = c / a - b;");
+
+        let stack_compiler = Compiler::new();
+        let mut vm = VirtualMachine::new(&stack_compiler.string_pool);
+        vm.try_execute(&stack_compiler.compile(&expressions)).unwrap();
+
+        let register_compiler = Compiler::new();
+        let program = register_compiler.compile_registers(&expressions);
+        let mut register_vm = RegisterMachine::new(&register_compiler.string_pool);
+        register_vm.try_execute(&program).unwrap();
+
+        assert_eq!(&register_vm.variables(), vm.variables());
+        assert_eq!(*register_compiler.string_pool.borrow(), *stack_compiler.string_pool.borrow());
+    }
+
+    #[test]
+    fn variables_are_registers_and_temporaries_follow_them() {
+        let compiler = Compiler::new();
+        let program = compiler.compile_registers(&parse("x = 5; y = x * 3; x = x + 1; y = (x - y) * ((y + x) / 2);"));
+        assert_eq!(program.ops, vec![
+            RegisterOp::Const(0, 5),
+            RegisterOp::Const(2, 3),
+            RegisterOp::Mul(1, 0, 2),
+            RegisterOp::AddImm(0, 0, 1),
+            RegisterOp::Sub(2, 0, 1),
+            RegisterOp::Add(3, 1, 0),
+            RegisterOp::Const(4, 2),
+            RegisterOp::Div(3, 3, 4),
+            RegisterOp::Mul(1, 2, 3)
+        ]);
+        assert_eq!(program.registers, 5);
+    }
+
+    #[test]
+    fn registers_persist_between_programs() {
+        let compiler = Compiler::new();
+        let mut vm = RegisterMachine::new(&compiler.string_pool);
+        vm.try_execute(&compiler.compile_registers(&parse("x = 40;"))).unwrap();
+        vm.try_execute(&compiler.compile_registers(&parse("y = x + 2; z = y;"))).unwrap();
+        assert_eq!(vm.registers()[..3], [40, 42, 42]);
+        assert_eq!(compiler.compile_registers(&parse("z = y;")).ops, vec![RegisterOp::Move(2, 1)]);
+    }
+}
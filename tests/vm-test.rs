@@ -0,0 +1,307 @@
+/*
+vm-test.rs: Tests for the CCIL Virtual Machine
+Copyright (C) 2025-26 The CCIL Developers
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use ccil::vm::{RunStatus, VirtualMachine};
+    use ccil::vm::chunk::{Chunk, ChunkError, OperandEncoding};
+    use ccil::vm::decoded::DecodedChunk;
+    use ccil::vm::memory::VARIABLE_BYTES;
+    use ccil::vm::opcode::{Op, OpCodeLookup};
+    use ccil::vm::section::{Executable, SymbolTable};
+    use ccil::vm::variable_value::VariableValue;
+
+    fn assemble(ops: &[(&str, &[i32])]) -> Vec<u8> {
+        let lookup = OpCodeLookup::new();
+        let mut chunk = Vec::<u8>::new();
+        for (symbol, args) in ops {
+            chunk.write_op(lookup.from_symbol(symbol).unwrap());
+            for arg in *args {
+                chunk.write_arg(*arg);
+            }
+        }
+        chunk
+    }
+
+    fn executable(code: Vec<u8>, functions: &[(&str, usize)]) -> Executable {
+        Executable {
+            code,
+            string_pool: Vec::new(),
+            symbols: SymbolTable {
+                functions: functions.iter().map(|(name, offset)| (name.to_string(), *offset)).collect(),
+                variables: Vec::new()
+            },
+            encoding: OperandEncoding::Fixed
+        }
+    }
+
+    #[test]
+    fn invoke_function_returns_results() {
+        // jump over the function, which moves 42 below the return address
+        let program = executable(assemble(&[
+            ("JUMP", &[20]),
+            ("CONST", &[42]),
+            ("ROT", &[1]),
+            ("RETURN", &[0])
+        ]), &[("answer", 5)]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert_eq!(vm.invoke_function(&program, "answer", &[]).unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn invoke_function_passes_arguments_in_calling_order() {
+        // stack on entry is b, a, return address
+        let program = executable(assemble(&[
+            ("COPY", &[1]),
+            ("COPY", &[3]),
+            ("SUB", &[]),
+            ("ROT", &[3]),
+            ("RETURN", &[0])
+        ]), &[("sub", 0)]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert_eq!(vm.invoke_function(&program, "sub", &[10, 3]).unwrap(), vec![7, 3, 10]);
+    }
+
+    #[test]
+    fn invoke_function_rejects_unknown_names() {
+        let program = executable(vec![0x00], &[]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert!(vm.invoke_function(&program, "missing", &[]).is_err());
+    }
+
+    #[test]
+    fn invoke_function_returns_runtime_errors_and_restores_the_stack() {
+        let program = executable(assemble(&[
+            ("COPY", &[1]),
+            ("CONST", &[0]),
+            ("DIV", &[]),
+            ("RETURN", &[1])
+        ]), &[("divide", 0)]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.try_execute(&assemble(&[("CONST", &[7])])).unwrap();
+        assert_eq!(vm.invoke_function(&program, "divide", &[5]).unwrap_err(), "Error at chunk offset 10: division by zero");
+        assert_eq!(format!("{:?}", vm.stack()), "[7]");
+    }
+
+    #[test]
+    fn invoke_function_rejects_offsets_past_the_code() {
+        let program = executable(assemble(&[("RETURN", &[0])]), &[("past", 5), ("end", 6)]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert_eq!(vm.invoke_function(&program, "past", &[]).unwrap_err(), "Function past at offset 5 is past the end of the code");
+        assert!(vm.invoke_function(&program, "end", &[1]).is_err());
+        assert_eq!(format!("{:?}", vm.stack()), "[]");
+    }
+
+    #[test]
+    fn decoding_resolves_branches_to_instruction_indices() {
+        let chunk = assemble(&[
+            ("CONST", &[3]),    // 0
+            ("CONST", &[1]),    // 5
+            ("SUB", &[]),       // 10
+            ("COPY", &[0]),     // 11
+            ("IFNZ", &[5]),     // 16
+            ("CALL", &[27]),    // 21
+            ("POP", &[]),       // 26
+            ("JUMP", &[-1])     // 27
+        ]);
+        let program = DecodedChunk::decode(&chunk, OperandEncoding::Fixed, &OpCodeLookup::new()).unwrap();
+        assert_eq!(program.ops[4], Op::Ifnz(1));
+        assert_eq!(program.ops[5], Op::Call(7));
+        // branches past the end leave the chunk
+        assert_eq!(program.ops[7], Op::Jump(8));
+        assert_eq!(program.offsets, vec![0, 5, 10, 11, 16, 21, 26, 27, 32]);
+        assert_eq!((program.index_of(21), program.index_of(22), program.index_of(100)), (Some(5), None, Some(8)));
+        assert_eq!(program.offset_of(8), 32);
+    }
+
+    #[test]
+    fn decoding_rejects_branches_into_instructions() {
+        let chunk = assemble(&[("CONST", &[0]), ("IFZ", &[2])]);
+        let error = DecodedChunk::decode(&chunk, OperandEncoding::Fixed, &OpCodeLookup::new()).unwrap_err();
+        assert!(matches!(error, ChunkError::MisalignedBranch { offset: 5, target: 2 }));
+
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert!(vm.try_execute(&chunk).unwrap_err().contains("not the start of an instruction"));
+    }
+
+    #[test]
+    fn runtime_errors_report_byte_offsets() {
+        // the call returns to the NOP at 5, then the RETURN at 6 runs again with an empty stack
+        let chunk = assemble(&[("CALL", &[6]), ("NOP", &[]), ("RETURN", &[0])]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert_eq!(vm.try_execute(&chunk).unwrap_err(), "Error at chunk offset 6: stack underflow at offset 0");
+    }
+
+    #[test]
+    fn superinstructions_match_the_pairs_they_replace() {
+        let chunk = assemble(&[
+            ("CONST", &[5]),        // 0
+            ("STORE", &[0, 4]),     // 5
+            ("INCVAR", &[0, 41]),   // 14
+            ("LOAD", &[0]),         // 23
+            ("ADDC", &[-40]),       // 28
+            ("COPYIFZ", &[0]),      // 33
+            ("COPYIFNZ", &[43]),    // 38
+            ("CONST", &[7])         // 43
+        ]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.try_execute(&chunk).unwrap();
+        // the boolean loads as 1, and the increment stores a number
+        assert_eq!(vm.variables()[&0], VariableValue::Number(42));
+        assert_eq!(format!("{:?}", vm.stack()), "[2, 7]");
+
+        let mut vm = VirtualMachine::new(&string_pool);
+        assert_eq!(vm.try_execute(&assemble(&[("COPYIFZ", &[0])])).unwrap_err(), "Error at chunk offset 0: stack underflow at offset 0");
+    }
+
+    #[test]
+    fn unbounded_recursion_overflows_the_stack() {
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_max_stack_depth(100);
+        // every call pushes a return address and never returns
+        assert_eq!(vm.try_execute(&assemble(&[("CALL", &[0])])).unwrap_err(), "Error at chunk offset 0: stack overflow");
+        assert_eq!((vm.stack_depth(), vm.peak_stack_depth()), (100, 100));
+    }
+
+    #[test]
+    fn reaching_past_the_bottom_underflows() {
+        let string_pool = RefCell::new(Vec::new());
+        for (ops, error) in [
+            (&[("CONST", &[1][..]), ("COPY", &[1][..])][..], "Error at chunk offset 5: stack underflow at offset 1"),
+            (&[("CONST", &[1]), ("COPY", &[-1])], "Error at chunk offset 5: stack underflow at offset -1"),
+            (&[("CONST", &[1]), ("CONST", &[2]), ("ROT", &[2])], "Error at chunk offset 10: stack underflow at offset 2"),
+            (&[("CONST", &[1]), ("DROP", &[2])], "Error at chunk offset 5: stack underflow at offset 0")
+        ] {
+            let mut vm = VirtualMachine::new(&string_pool);
+            assert_eq!(vm.try_execute(&assemble(ops)).unwrap_err(), error);
+        }
+
+        // ROT may move the top item all the way to the bottom
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.try_execute(&assemble(&[("CONST", &[1]), ("CONST", &[2]), ("CONST", &[3]), ("ROT", &[2]), ("POP", &[])])).unwrap();
+        assert_eq!(format!("{:?}", vm.stack()), "[3, 1]");
+        assert_eq!((vm.stack_depth(), vm.peak_stack_depth()), (2, 3));
+    }
+
+    #[test]
+    fn invalid_scripts_fail_without_panicking() {
+        let string_pool = RefCell::new(b"hi\0no".to_vec());
+        for (ops, error) in [
+            (&[("CONST", &[-1][..]), ("RETURN", &[0][..])][..], "Error at chunk offset 5: Return address -1 is not the start of an instruction"),
+            (&[("CONST", &[1]), ("STORE", &[0, 3])], "Error at chunk offset 5: Cannot store a value with type id 3"),
+            (&[("CONST", &[1]), ("STORE", &[0, -1])], "Error at chunk offset 5: Cannot store a value with type id -1"),
+            (&[("CONST", &[1]), ("STORE", &[0, 99])], "Error at chunk offset 5: Cannot store a value with type id 99"),
+            (&[("LOAD", &[3])], "Error at chunk offset 0: Attempted to access valueless variable 3"),
+            (&[("INCVAR", &[3, 1])], "Error at chunk offset 0: Attempted to access valueless variable 3"),
+            (&[("CONST", &[1]), ("WRITE", &[0])], "Error at chunk offset 5: Cannot write to STDIN"),
+            (&[("CONST", &[1]), ("WRITE", &[3])], "Error at chunk offset 5: Cannot write to file 3"),
+            (&[("CONST", &[1]), ("WRITE", &[-1])], "Error at chunk offset 5: Cannot write to file -1"),
+            (&[("CONST", &[9]), ("WRITES", &[1])], "Error at chunk offset 5: String index 9 is outside the string pool"),
+            (&[("CONST", &[-1]), ("WRITES", &[1])], "Error at chunk offset 5: String index 18446744073709551615 is outside the string pool"),
+            (&[("CONST", &[3]), ("WRITES", &[1])], "Error at chunk offset 5: String at index 3 is not null-terminated")
+        ] {
+            let mut vm = VirtualMachine::new(&string_pool);
+            assert_eq!(vm.try_execute(&assemble(ops)).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn running_out_of_fuel_suspends_execution() {
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_fuel(Some(1000));
+        assert_eq!(vm.try_execute(&assemble(&[("JUMP", &[0])])), Ok(RunStatus::OutOfFuel));
+        assert_eq!(vm.fuel(), Some(0));
+        assert_eq!(vm.resume(10), Ok(RunStatus::OutOfFuel));
+        // a new run drops the suspended one
+        vm.set_fuel(None);
+        assert_eq!(vm.try_execute(&assemble(&[("NOP", &[])])), Ok(RunStatus::Finished));
+        assert!(vm.resume(10).is_err());
+    }
+
+    #[test]
+    fn resuming_finishes_the_same_way() {
+        // counts 10 down to 0 in 2 + 4 * 10 instructions
+        let chunk = assemble(&[("CONST", &[10]), ("CONST", &[1]), ("SUB", &[]), ("COPY", &[0]), ("IFNZ", &[5]), ("POP", &[])]);
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_fuel(Some(5));
+        let mut status = vm.try_execute(&chunk).unwrap();
+        let mut resumes = 0;
+        while status == RunStatus::OutOfFuel {
+            assert_eq!(vm.fuel(), Some(0));
+            status = vm.resume(5).unwrap();
+            resumes += 1;
+        }
+        assert_eq!((resumes, vm.fuel()), (8, Some(3)));
+        assert_eq!(vm.stack_depth(), 0);
+
+        // errors after resuming still report where they happened
+        vm.set_fuel(Some(1));
+        assert_eq!(vm.try_execute(&assemble(&[("NOP", &[]), ("POP", &[])])), Ok(RunStatus::OutOfFuel));
+        assert_eq!(vm.resume(1).unwrap_err(), "Error at chunk offset 1: stack underflow at offset 0");
+    }
+
+    #[test]
+    fn variables_are_charged_once_against_the_memory_limit() {
+        let string_pool = RefCell::new(Vec::new());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_memory_limit(Some(2 * VARIABLE_BYTES));
+        let chunk = assemble(&[
+            ("CONST", &[1]), ("STORE", &[0, 1]),    // 0, 5
+            ("CONST", &[2]), ("STORE", &[0, 1]),    // 14, 19
+            ("INCVAR", &[0, 1]),                    // 28
+            ("CONST", &[3]), ("STORE", &[1, 1]),    // 37, 42
+            ("CONST", &[4]), ("STORE", &[2, 1])     // 51, 56
+        ]);
+        let error = vm.try_execute(&chunk).unwrap_err();
+        assert_eq!(error, format!(
+            "Error at chunk offset 56: out of memory: {} more bytes requested with {} of the {} byte limit in use",
+            VARIABLE_BYTES, 2 * VARIABLE_BYTES, 2 * VARIABLE_BYTES
+        ));
+        assert_eq!(vm.variables().len(), 2);
+        assert_eq!(vm.memory_used(), 2 * VARIABLE_BYTES);
+    }
+
+    #[test]
+    fn the_string_pool_is_charged_as_it_grows() {
+        let string_pool = RefCell::new(b"hi\0".to_vec());
+        let mut vm = VirtualMachine::new(&string_pool);
+        vm.set_memory_limit(Some(5));
+        vm.try_execute(&assemble(&[("NOP", &[])])).unwrap();
+        assert_eq!(vm.memory_used(), 3);
+
+        string_pool.borrow_mut().extend(b"you\0");
+        let error = vm.try_execute(&assemble(&[("NOP", &[])])).unwrap_err();
+        assert_eq!(error, "Failed to load string pool: out of memory: 4 more bytes requested with 3 of the 5 byte limit in use");
+        vm.set_memory_limit(None);
+        vm.try_execute(&assemble(&[("NOP", &[])])).unwrap();
+        assert_eq!(vm.memory_used(), 7);
+    }
+}